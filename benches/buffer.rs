@@ -0,0 +1,132 @@
+//! Measures the cost of `ScreenBuffer` refresh on a large screen
+//!
+//! Run with `cargo bench --features bench`. Writing the same text to every line forces each
+//! line to be compared with the back buffer, as was done on every refresh
+//! before changed lines were tracked; compare it with the cost of a refresh
+//! after changing a single cell.
+
+extern crate mortal;
+
+use std::time::{Duration, Instant};
+
+use mortal::Size;
+use mortal::ScreenBuffer;
+
+const SIZE: Size = Size{lines: 100, columns: 300};
+
+fn main() {
+    let mut buf = ScreenBuffer::new(SIZE);
+    let lines = make_lines('a');
+
+    draw(&mut buf, &lines);
+    refresh(&mut buf);
+
+    bench("full redraw", || {
+        draw(&mut buf, &make_lines('b'));
+        refresh(&mut buf);
+        draw(&mut buf, &lines);
+        refresh(&mut buf)
+    });
+
+    let mut toggle = false;
+
+    bench("rewrite all lines, one cell changed", || {
+        toggle = !toggle;
+        draw(&mut buf, &lines);
+        buf.write_at((50, 150).into(), if toggle { "x" } else { "y" }).unwrap();
+        refresh(&mut buf)
+    });
+
+    bench("one cell changed", || {
+        toggle = !toggle;
+        buf.write_at((50, 150).into(), if toggle { "x" } else { "y" }).unwrap();
+        refresh(&mut buf)
+    });
+
+    let scrolled = (1..SIZE.lines).map(|i| lines[i].clone())
+        .chain(Some(make_line('z', 0)))
+        .collect::<Vec<_>>();
+
+    bench("scroll one line", || {
+        draw(&mut buf, &scrolled);
+        let n = refresh(&mut buf);
+        draw(&mut buf, &lines);
+        n + refresh(&mut buf)
+    });
+
+    let combined = lines.iter()
+        .map(|line| line.replace('a', "a\u{301}"))
+        .collect::<Vec<_>>();
+
+    bench("full redraw, combining marks", || {
+        draw(&mut buf, &combined);
+        let n = refresh(&mut buf);
+        draw(&mut buf, &lines);
+        n + refresh(&mut buf)
+    });
+
+    let small = Size{lines: SIZE.lines - 1, columns: SIZE.columns - 1};
+
+    bench("resize", || {
+        buf.resize(small);
+        buf.resize(SIZE);
+        0
+    });
+}
+
+fn bench<F: FnMut() -> usize>(name: &str, mut f: F) {
+    const WARM_UP: usize = 10;
+    const TARGET: Duration = Duration::from_secs(1);
+
+    let mut cells = 0;
+
+    for _ in 0..WARM_UP {
+        cells += f();
+    }
+
+    let start = Instant::now();
+    let mut iters = 0;
+
+    while start.elapsed() < TARGET {
+        cells += f();
+        iters += 1;
+    }
+
+    let per_iter = start.elapsed() / iters;
+
+    println!("{:<40} {:>12?}/iter ({} cells drawn)",
+        name, per_iter, cells / (iters as usize + WARM_UP));
+}
+
+fn make_line(ch: char, n: usize) -> String {
+    (0..SIZE.columns)
+        .map(|i| if (i + n) % 10 == 0 { ' ' } else { ch })
+        .collect()
+}
+
+fn make_lines(ch: char) -> Vec<String> {
+    (0..SIZE.lines).map(|n| make_line(ch, n)).collect()
+}
+
+fn draw(buf: &mut ScreenBuffer, lines: &[String]) {
+    for (line, text) in lines.iter().enumerate() {
+        buf.write_at((line, 0).into(), text).unwrap();
+    }
+}
+
+// Performs the same work as `Screen::refresh`, without writing to a terminal;
+// returns the number of changed cells.
+fn refresh(buf: &mut ScreenBuffer) -> usize {
+    if let Some(scroll) = buf.find_scroll() {
+        buf.apply_scroll(&scroll);
+    }
+
+    let mut indices = buf.indices();
+    let mut n = 0;
+
+    while buf.next_cell(&mut indices).is_some() {
+        n += 1;
+    }
+
+    n
+}
@@ -3,6 +3,7 @@
 extern crate mortal;
 
 use std::io;
+use std::time::Duration;
 
 use mortal::{Event, Key, PrepareConfig, Terminal};
 
@@ -12,6 +13,11 @@ fn main() -> io::Result<()> {
     // Prepare to read from the terminal.
     let state = term.prepare(PrepareConfig{
         enable_mouse: true,
+        enable_bracketed_paste: true,
+        enable_focus_events: true,
+        enable_kitty_keyboard: true,
+        escape_timeout: Some(Duration::from_millis(50)),
+        click_interval: Some(Duration::from_millis(500)),
         .. Default::default()
     })?;
 
@@ -38,6 +38,28 @@ pub fn main() -> io::Result<()> {
         [#yellow] "yellow"
         [reset])?;
 
+    term_writeln!(term,
+        [bright_black] "black "
+        [bright_blue] "blue "
+        [bright_cyan] "cyan "
+        [bright_green] "green "
+        [bright_magenta] "magenta "
+        [bright_red] "red "
+        [bright_white] "white "
+        [bright_yellow] "yellow"
+        [reset])?;
+
+    term_writeln!(term,
+        [#bright_black] "black "
+        [#bright_blue] "blue "
+        [#bright_cyan] "cyan "
+        [#bright_green] "green "
+        [#bright_magenta] "magenta "
+        [#bright_red] "red "
+        [#bright_white] "white "
+        [#bright_yellow] "yellow"
+        [reset])?;
+
     term_writeln!(term,
         [bold] "bold " [!bold]
         [italic] "italic " [!italic]
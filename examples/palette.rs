@@ -0,0 +1,54 @@
+//! Displays the 256-color palette and a 24-bit color gradient
+
+extern crate mortal;
+
+use std::io;
+
+use mortal::{Color, Terminal};
+
+fn main() -> io::Result<()> {
+    let term = Terminal::new()?;
+
+    // Basic and bright colors
+    for n in 0..16 {
+        term.set_bg(Color::Indexed(n))?;
+        term.write_str("  ")?;
+    }
+
+    term.clear_attributes()?;
+    writeln!(term)?;
+
+    // 6x6x6 color cube
+    for row in 0..6 {
+        for n in 0..36 {
+            term.set_bg(Color::Indexed(16 + row * 36 + n))?;
+            term.write_str(" ")?;
+        }
+
+        term.clear_attributes()?;
+        writeln!(term)?;
+    }
+
+    // Grayscale ramp
+    for n in 232..=255 {
+        term.set_bg(Color::Indexed(n))?;
+        term.write_str(" ")?;
+    }
+
+    term.clear_attributes()?;
+    writeln!(term)?;
+
+    // 24-bit color gradient
+    for n in 0..64 {
+        let r = (n * 4) as u8;
+        let b = 255 - r;
+
+        term.set_bg(Color::Rgb(r, 0x40, b))?;
+        term.write_str(" ")?;
+    }
+
+    term.clear_attributes()?;
+    writeln!(term)?;
+
+    Ok(())
+}
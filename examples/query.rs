@@ -0,0 +1,42 @@
+//! Queries the terminal for information about its features
+
+extern crate mortal;
+
+use std::io;
+use std::time::Duration;
+
+use mortal::{PrepareConfig, Query, Terminal};
+
+fn main() -> io::Result<()> {
+    let term = Terminal::new()?;
+
+    let state = term.prepare(PrepareConfig::default())?;
+
+    let queries = [
+        Query::PrimaryDeviceAttributes,
+        Query::SecondaryDeviceAttributes,
+        Query::Version,
+        Query::Capability("TN".to_owned()),
+        Query::Mode(2026),
+        Query::ForegroundColor,
+        Query::BackgroundColor,
+    ];
+
+    let mut replies = Vec::new();
+
+    for query in &queries {
+        let reply = term.query(query, Some(Duration::from_millis(200)))?;
+        replies.push((query, reply));
+    }
+
+    term.restore(state)?;
+
+    for (query, reply) in replies {
+        match reply {
+            Some(reply) => println!("{:?}: {:?}", query, reply),
+            None => println!("{:?}: no reply", query),
+        }
+    }
+
+    Ok(())
+}
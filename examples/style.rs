@@ -2,7 +2,7 @@ extern crate mortal;
 
 use std::io;
 
-use mortal::{Style, Terminal};
+use mortal::{Color, Style, Terminal, UnderlineStyle};
 
 fn main() -> io::Result<()> {
     let term = Terminal::new()?;
@@ -22,5 +22,26 @@ fn main() -> io::Result<()> {
     term.write_styled(None, None, Style::UNDERLINE,
         "underlined text\n")?;
 
+    term.set_underline_style(UnderlineStyle::Curly)?;
+    term.set_underline_color(Color::Red)?;
+    term.write_styled(None, None, Style::UNDERLINE,
+        "curly red underlined text\n")?;
+
+    term.write_styled(None, None, Style::DIM,
+        "dim text\n")?;
+
+    term.write_styled(None, None, Style::BLINK,
+        "blinking text\n")?;
+
+    term.write_styled(None, None, Style::STRIKETHROUGH,
+        "strikethrough text\n")?;
+
+    term.write_styled(None, None, Style::OVERLINE,
+        "overlined text\n")?;
+
+    term.write_styled(None, None, Style::HIDDEN,
+        "hidden text")?;
+    term.write_str(" <- hidden text\n")?;
+
     Ok(())
 }
@@ -23,7 +23,7 @@ fn main() -> io::Result<()> {
     println!();
     println!();
 
-    write_data(&mut term, n_events, last_event)?;
+    write_data(&mut term, n_events, last_event.as_ref())?;
 
     loop {
         if let Some(ev) = term.read_event(None)? {
@@ -31,12 +31,14 @@ fn main() -> io::Result<()> {
                 continue;
             }
 
+            let quit = ev == Event::Key(Key::Char('q'));
+
             n_events += 1;
             last_event = Some(ev);
 
-            write_data(&mut term, n_events, last_event)?;
+            write_data(&mut term, n_events, last_event.as_ref())?;
 
-            if let Event::Key(Key::Char('q')) = ev {
+            if quit {
                 break;
             }
         }
@@ -48,7 +50,7 @@ fn main() -> io::Result<()> {
     Ok(())
 }
 
-fn write_data(term: &mut Terminal, n_events: usize, last_event: Option<Event>)
+fn write_data(term: &mut Terminal, n_events: usize, last_event: Option<&Event>)
         -> io::Result<()> {
     // Move the cursor up 2 lines.
     term.move_up(2)?;
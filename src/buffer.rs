@@ -1,14 +1,19 @@
-use std::mem::swap;
-use std::ops::Range;
-
-use smallstr::SmallString;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::mem;
+use std::num::NonZeroU32;
+use std::ops::{Deref, Range};
+use std::str;
 
 use crate::priv_util::is_visible;
-use crate::terminal::{Color, Cursor, Size, Style, Theme};
+use crate::terminal::{Color, Cursor, Size, Style, Theme, UnderlineStyle};
 use crate::util::{char_width, is_combining_mark};
 
 const TAB_STOP: usize = 8;
 
+// Minimum number of pooled strings before unused strings are collected
+const MIN_POOL_LIMIT: usize = 64;
+
 pub struct ScreenBuffer {
     buffer: Vec<Cell>,
     back_buffer: Vec<Cell>,
@@ -18,6 +23,19 @@ pub struct ScreenBuffer {
     fg: Option<Color>,
     bg: Option<Color>,
     style: Style,
+    underline: UnderlineStyle,
+    underline_color: Option<Color>,
+    link: Option<StrId>,
+
+    // Grapheme clusters and links referenced by cells in either buffer
+    pool: Pool,
+    // Number of pooled strings at which unused strings are collected
+    pool_limit: usize,
+
+    // Range of columns in each line which may differ from the back buffer
+    dirty: Vec<Range<usize>>,
+    // Hash of each line in the back buffer, if computed since it last changed
+    back_hashes: Vec<Option<u64>>,
 }
 
 impl ScreenBuffer {
@@ -33,6 +51,15 @@ impl ScreenBuffer {
             fg: None,
             bg: None,
             style: Style::empty(),
+            underline: UnderlineStyle::Single,
+            underline_color: None,
+            link: None,
+
+            pool: Pool::default(),
+            pool_limit: MIN_POOL_LIMIT,
+
+            dirty: vec![0..0; size.lines],
+            back_hashes: vec![None; size.lines],
         }
     }
 
@@ -52,6 +79,9 @@ impl ScreenBuffer {
         // Screen implementations will clear the screen and redraw.
         new_buffer(&mut self.back_buffer, new_size);
         self.size = new_size;
+
+        self.back_hashes = vec![None; new_size.lines];
+        self.mark_all_dirty();
     }
 
     pub fn set_cursor(&mut self, pos: Cursor) {
@@ -67,6 +97,8 @@ impl ScreenBuffer {
         self.fg = None;
         self.bg = None;
         self.style = Style::empty();
+        self.underline = UnderlineStyle::Single;
+        self.underline_color = None;
     }
 
     pub fn add_style(&mut self, style: Style) {
@@ -89,16 +121,167 @@ impl ScreenBuffer {
         self.bg = bg;
     }
 
+    pub fn set_underline_style(&mut self, underline: UnderlineStyle) {
+        self.underline = underline;
+    }
+
+    pub fn set_underline_color(&mut self, color: Option<Color>) {
+        self.underline_color = color;
+    }
+
+    pub fn set_link(&mut self, link: Option<&str>) {
+        self.link = link.map(|link| self.intern(link));
+    }
+
     pub fn set_theme(&mut self, theme: Theme) {
         self.set_fg(theme.fg);
         self.set_bg(theme.bg);
         self.set_style(theme.style);
+        self.set_underline_style(theme.underline);
+        self.set_underline_color(theme.underline_color);
     }
 
     pub fn clear_screen(&mut self) {
         for cell in &mut self.buffer {
             *cell = Cell::default();
         }
+
+        self.mark_all_dirty();
+    }
+
+    fn mark_dirty(&mut self, pos: Cursor) {
+        let dirty = &mut self.dirty[pos.line];
+
+        if dirty.start == dirty.end {
+            *dirty = pos.column..pos.column + 1;
+        } else {
+            dirty.start = dirty.start.min(pos.column);
+            dirty.end = dirty.end.max(pos.column + 1);
+        }
+    }
+
+    fn mark_all_dirty(&mut self) {
+        let columns = self.size.columns;
+
+        for dirty in &mut self.dirty {
+            *dirty = 0..columns;
+        }
+
+        self.dirty.resize(self.size.lines, 0..columns);
+    }
+
+    // Finds the vertical shift of lines between the back buffer and buffer
+    // which would save the most lines from being redrawn.
+    pub fn find_scroll(&mut self) -> Option<Scroll> {
+        let Size{lines, columns} = self.size;
+
+        // Scrolling to keep a single changed line would expose another line,
+        // which must then be redrawn; at least two lines must have changed.
+        if lines < 2 || columns == 0 ||
+                self.dirty.iter().filter(|d| d.start != d.end).nth(1).is_none() {
+            return None;
+        }
+
+        let old = (0..lines).map(|i| self.back_hash(i)).collect::<Vec<_>>();
+
+        // Lines which are not dirty are equal to those in the back buffer
+        let new = (0..lines)
+            .map(|i| if self.dirty[i].start == self.dirty[i].end {
+                old[i]
+            } else {
+                hash_line(&self.buffer[i * columns..(i + 1) * columns])
+            })
+            .collect::<Vec<_>>();
+
+        let mut best = None;
+        let mut best_gain = 0;
+
+        for shift in 1..lines as isize {
+            for &n in &[shift, -shift] {
+                // Start and number of changed lines in the current run
+                // of lines where `new[i] == old[i + n]`
+                let mut run: Option<(usize, usize)> = None;
+
+                for i in 0..=lines {
+                    let src = i as isize + n;
+
+                    if i < lines && src >= 0 && (src as usize) < lines &&
+                            new[i] == old[src as usize] {
+                        let (_, gain) = run.get_or_insert((i, 0));
+
+                        if new[i] != old[i] {
+                            *gain += 1;
+                        }
+                    } else if let Some((start, saved)) = run.take() {
+                        let scroll = if n > 0 {
+                            Scroll{top: start, bottom: i + n as usize, n}
+                        } else {
+                            Scroll{top: start - (-n) as usize, bottom: i, n}
+                        };
+
+                        // Lines exposed by the scroll which were already
+                        // correct must now be redrawn.
+                        let (kept_start, kept_end) = scroll.kept_lines();
+                        let lost = (scroll.top..kept_start).chain(kept_end..scroll.bottom)
+                            .filter(|&j| new[j] == old[j])
+                            .count();
+
+                        let gain = saved as isize - lost as isize;
+
+                        if gain > best_gain {
+                            best_gain = gain;
+                            best = Some(scroll);
+                        }
+                    }
+                }
+            }
+        }
+
+        best.filter(|scroll| self.verify_scroll(scroll))
+    }
+
+    fn back_hash(&mut self, line: usize) -> u64 {
+        let columns = self.size.columns;
+        let back_buffer = &self.back_buffer;
+
+        *self.back_hashes[line].get_or_insert_with(
+            || hash_line(&back_buffer[line * columns..(line + 1) * columns]))
+    }
+
+    // Checks that the lines matched by hash are actually equal
+    fn verify_scroll(&self, scroll: &Scroll) -> bool {
+        let columns = self.size.columns;
+        let (start, end) = scroll.kept_lines();
+
+        (start..end).all(|i| {
+            let src = (i as isize + scroll.n) as usize;
+
+            self.buffer[i * columns..(i + 1) * columns] ==
+                self.back_buffer[src * columns..(src + 1) * columns]
+        })
+    }
+
+    // Shifts lines in the back buffer to reflect a scroll performed on
+    // the terminal. Lines exposed by the scroll are blank.
+    pub fn apply_scroll(&mut self, scroll: &Scroll) {
+        let columns = self.size.columns;
+        let region = &mut self.back_buffer[scroll.top * columns..scroll.bottom * columns];
+        let n = scroll.n.unsigned_abs() * columns;
+
+        if scroll.n > 0 {
+            region.rotate_left(n);
+            let len = region.len();
+            fill_default(&mut region[len - n..]);
+        } else {
+            region.rotate_right(n);
+            fill_default(&mut region[..n]);
+        }
+
+        // Lines in the region must be compared again
+        for line in scroll.top..scroll.bottom {
+            self.dirty[line] = 0..columns;
+            self.back_hashes[line] = None;
+        }
     }
 
     pub fn indices(&self) -> Range<usize> {
@@ -108,51 +291,189 @@ impl ScreenBuffer {
     // A wrapper type implementing Iterator would be ideal, but that would
     // interefere with Screen implementations calling `&mut self` methods.
     pub fn next_cell(&mut self, indices: &mut Range<usize>) -> Option<(Cursor, Cell)> {
-        while let Some(idx) = indices.next() {
-            let first = self.buffer[idx].first_char();
-            let width = char_width(first).unwrap_or(0);
+        let columns = self.size.columns;
+
+        while indices.start < indices.end {
+            let idx = indices.start;
+            let line = idx / columns;
+            let column = idx % columns;
+            let dirty = self.dirty[line].clone();
+
+            // Skip the remainder of the line if it is unchanged
+            if column >= dirty.end {
+                self.dirty[line] = 0..0;
+                indices.start = (line + 1) * columns;
+                continue;
+            }
+
+            // Skip to the first changed cell; or to the wide character
+            // overlapping it, as overlapped cells are never drawn.
+            if column < dirty.start {
+                let mut start = line * columns + dirty.start;
+
+                if self.buffer[start - 1].is_wide() {
+                    start -= 1;
+                }
+
+                if idx < start {
+                    indices.start = start;
+                    continue;
+                }
+            }
+
+            indices.next();
 
             // Skip cells overlapped by wide characters
-            if width == 2 {
+            if self.buffer[idx].is_wide() {
                 let _ = indices.next();
             }
 
+            self.mark_clean(line, indices.start);
+
             if self.buffer[idx] != self.back_buffer[idx] {
-                let cell = self.buffer[idx].clone();
+                self.back_buffer[idx] = self.buffer[idx];
+                self.back_hashes[line] = None;
+
+                return Some((Cursor{line, column}, self.buffer[idx]));
+            }
+        }
+
+        None
+    }
+
+    // Returns the number of cells, beginning at `pos` and ending before
+    // the end of the line, which are equal to the cell at `pos`.
+    pub fn run_length(&self, pos: Cursor) -> usize {
+        let cells = self.cells(pos, self.size.columns - pos.column);
+
+        cells.iter().take_while(|&cell| *cell == cells[0]).count()
+    }
+
+    // Returns `n` cells beginning at `pos`
+    pub fn cells(&self, pos: Cursor, n: usize) -> &[Cell] {
+        let idx = pos.as_index(self.size);
+        &self.buffer[idx..idx + n]
+    }
+
+    // Marks the next `n` cells as drawn, as though they were
+    // returned from `next_cell`.
+    pub fn skip_cells(&mut self, indices: &mut Range<usize>, n: usize) {
+        for idx in indices.take(n) {
+            let line = idx / self.size.columns;
 
-                let line = idx / self.size.columns;
-                let column = idx % self.size.columns;
+            self.back_buffer[idx] = self.buffer[idx];
+            self.back_hashes[line] = None;
+            self.mark_clean(line, idx + 1);
+        }
+    }
 
-                self.back_buffer[idx] = cell.clone();
+    // Marks cells in `line` before the index `next` as unchanged
+    fn mark_clean(&mut self, line: usize, next: usize) {
+        let dirty = &mut self.dirty[line];
+        let column = next - line * self.size.columns;
+
+        dirty.start = dirty.start.max(column.min(dirty.end));
+    }
 
-                return Some((Cursor{line, column}, cell));
+    // Returns the text of a cell from this buffer
+    pub fn text(&self, cell: &Cell) -> CellText<'_> {
+        match cell.glyph.char() {
+            Some(ch) => CellText::new(ch),
+            None => match cell.glyph.cluster_id() {
+                Some(id) => CellText::Cluster(self.pool.get(id)),
+                None => CellText::Cluster(""),
             }
         }
+    }
 
-        None
+    // Returns the link target of a cell from this buffer
+    pub fn link(&self, cell: &Cell) -> Option<&str> {
+        cell.link.map(|id| self.pool.get(id))
+    }
+
+    fn intern(&mut self, s: &str) -> StrId {
+        if let Some(id) = self.pool.find(s) {
+            return id;
+        }
+
+        if self.pool.len() >= self.pool_limit {
+            self.collect_pool();
+            self.pool_limit = MIN_POOL_LIMIT.max(self.pool.len() * 2);
+        }
+
+        self.pool.insert(s)
+    }
+
+    // Removes strings which are no longer referenced from the pool
+    fn collect_pool(&mut self) {
+        let mut used = vec![false; self.pool.len()];
+
+        for cell in self.buffer.iter().chain(&self.back_buffer) {
+            if let Some(id) = cell.glyph.cluster_id() {
+                used[id.index()] = true;
+            }
+            if let Some(id) = cell.link {
+                used[id.index()] = true;
+            }
+        }
+
+        if let Some(id) = self.link {
+            used[id.index()] = true;
+        }
+
+        let ids = self.pool.retain(&used);
+        let remap = |id: StrId| ids[id.index()].expect("pooled string in use");
+
+        for cell in self.buffer.iter_mut().chain(&mut self.back_buffer) {
+            if let Some(id) = cell.glyph.cluster_id() {
+                cell.glyph = Glyph::cluster(remap(id), cell.is_wide());
+            }
+            cell.link = cell.link.map(remap);
+        }
+
+        self.link = self.link.map(remap);
+
+        // Line hashes depend on string identifiers
+        for hash in &mut self.back_hashes {
+            *hash = None;
+        }
     }
 
-    #[cfg(test)]
     fn cell(&self, pos: Cursor) -> &Cell {
         &self.buffer[pos.as_index(self.size)]
     }
 
     fn cell_mut(&mut self, pos: Cursor) -> &mut Cell {
+        self.mark_dirty(pos);
+
         let size = self.size;
         &mut self.buffer[pos.as_index(size)]
     }
 
     fn set_cell(&mut self, pos: Cursor, ch: char) {
-        let fg = self.fg;
-        let bg = self.bg;
-        let style = self.style;
+        let attrs = Theme{
+            fg: self.fg,
+            bg: self.bg,
+            style: self.style,
+            underline: self.underline,
+            underline_color: self.underline_color,
+        };
+
+        *self.cell_mut(pos) = Cell{
+            glyph: Glyph::new(ch),
+            link: self.link,
+            attrs,
+        };
+    }
 
-        let cell = self.cell_mut(pos);
+    fn push_char(&mut self, pos: Cursor, ch: char) {
+        let mut text = self.text(self.cell(pos)).to_owned();
+        text.push(ch);
 
-        cell.fg = fg;
-        cell.bg = bg;
-        cell.style = style;
-        cell.text = ch.into();
+        let wide = self.cell(pos).is_wide();
+        let id = self.intern(&text);
+
+        self.cell_mut(pos).glyph = Glyph::cluster(id, wide);
     }
 
     pub fn write_char(&mut self, ch: char) -> Result<(), OutOfBounds> {
@@ -172,16 +493,14 @@ impl ScreenBuffer {
         } else if is_combining_mark(ch) {
             if let Some(prev) = self.cursor.previous(self.size) {
                 self.try_cursor_at(prev)?;
-                self.cell_mut(prev).text.push(ch);
+                self.push_char(prev, ch);
             }
         } else if is_visible(ch) {
             self.try_cursor()?;
 
             if let Some(prev) = self.cursor.previous(self.size) {
-                let cell = self.cell_mut(prev);
-
-                if cell.is_wide() {
-                    *cell = Cell::default();
+                if self.cell(prev).is_wide() {
+                    *self.cell_mut(prev) = Cell::default();
                 }
             }
 
@@ -335,6 +654,23 @@ macro_rules! forward_screen_buffer_methods {
             $field.set_bg(bg);
         }
 
+        pub fn set_underline_style(&self,
+                underline: crate::terminal::UnderlineStyle) {
+            let $slf = self;
+            $field.set_underline_style(underline);
+        }
+
+        pub fn set_underline_color(&self,
+                color: Option<crate::terminal::Color>) {
+            let $slf = self;
+            $field.set_underline_color(color);
+        }
+
+        pub fn set_link(&self, link: Option<&str>) {
+            let $slf = self;
+            $field.set_link(link);
+        }
+
         pub fn set_theme(&self, theme: crate::terminal::Theme) {
             let $slf = self;
             $field.set_theme(theme)
@@ -429,6 +765,23 @@ macro_rules! forward_screen_buffer_mut_methods {
             $field.set_bg(bg);
         }
 
+        pub fn set_underline_style(&mut self,
+                underline: crate::terminal::UnderlineStyle) {
+            let $slf = self;
+            $field.set_underline_style(underline);
+        }
+
+        pub fn set_underline_color(&mut self,
+                color: Option<crate::terminal::Color>) {
+            let $slf = self;
+            $field.set_underline_color(color);
+        }
+
+        pub fn set_link(&mut self, link: Option<&str>) {
+            let $slf = self;
+            $field.set_link(link);
+        }
+
         pub fn set_theme(&mut self, theme: crate::terminal::Theme) {
             let $slf = self;
             $field.set_theme(theme);
@@ -468,54 +821,205 @@ macro_rules! forward_screen_buffer_mut_methods {
 #[derive(Debug)]
 pub struct OutOfBounds(());
 
-#[derive(Clone, Debug, Eq, PartialEq)]
-pub struct Cell {
-    fg: Option<Color>,
-    bg: Option<Color>,
-    style: Style,
-    text: SmallString<[u8; 8]>,
+// Describes lines in the range `top..bottom` moving up by `n` lines,
+// if `n` is positive, or down by `-n` lines, if `n` is negative.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct Scroll {
+    pub top: usize,
+    pub bottom: usize,
+    pub n: isize,
 }
 
-impl Cell {
-    fn new(fg: Option<Color>, bg: Option<Color>, style: Style, chr: char) -> Cell {
-        Cell{
-            fg,
-            bg,
-            style,
-            text: chr.into(),
+impl Scroll {
+    // Returns the range of destination lines whose contents are kept
+    fn kept_lines(&self) -> (usize, usize) {
+        let n = self.n.unsigned_abs();
+
+        if self.n > 0 {
+            (self.top, self.bottom - n)
+        } else {
+            (self.top + n, self.bottom)
         }
     }
+}
+
+// Cells refer to the text and link of the pool belonging to their buffer;
+// equal strings are pooled once, so cells may be compared directly.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub struct Cell {
+    glyph: Glyph,
+    link: Option<StrId>,
+    attrs: Theme,
+}
 
+impl Cell {
     fn invalid() -> Cell {
         Cell{
-            fg: None,
-            bg: None,
-            style: Style::empty(),
-            text: SmallString::new(),
+            glyph: Glyph::INVALID,
+            ..Cell::default()
         }
     }
 
-    pub fn attrs(&self) -> (Option<Color>, Option<Color>, Style) {
-        (self.fg, self.bg, self.style)
+    pub fn attrs(&self) -> Theme {
+        self.attrs
     }
 
-    pub fn text(&self) -> &str {
-        &self.text
+    // Returns the text of the cell, if it consists of a single char
+    pub fn char(&self) -> Option<char> {
+        self.glyph.char()
     }
 
-    fn first_char(&self) -> char {
-        self.text.chars().next().expect("empty cell text")
+    pub fn width(&self) -> usize {
+        if self.is_wide() { 2 } else { 1 }
     }
 
     fn is_wide(&self) -> bool {
-        self.text.chars().next()
-            .and_then(char_width).unwrap_or(0) == 2
+        self.glyph.0 & Glyph::WIDE != 0
     }
 }
 
 impl Default for Cell {
     fn default() -> Cell {
-        Cell::new(None, None, Style::empty(), ' ')
+        Cell{
+            glyph: Glyph::new(' '),
+            link: None,
+            attrs: Theme::default(),
+        }
+    }
+}
+
+// Text of a cell; either a single char, stored inline, or a cluster
+// of chars, stored in the pool. The high bits hold flags.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+struct Glyph(u32);
+
+impl Glyph {
+    // Value is a pooled string identifier, rather than a char
+    const CLUSTER: u32 = 1 << 31;
+    // The first char of the text is double width
+    const WIDE: u32 = 1 << 30;
+    const VALUE: u32 = Glyph::WIDE - 1;
+
+    // Never equal to a valid glyph
+    const INVALID: Glyph = Glyph(Glyph::VALUE);
+
+    fn new(ch: char) -> Glyph {
+        let wide = if char_width(ch) == Some(2) { Glyph::WIDE } else { 0 };
+        Glyph(ch as u32 | wide)
+    }
+
+    fn cluster(id: StrId, wide: bool) -> Glyph {
+        assert!(id.0.get() <= Glyph::VALUE, "too many pooled strings");
+        let wide = if wide { Glyph::WIDE } else { 0 };
+        Glyph(id.0.get() | Glyph::CLUSTER | wide)
+    }
+
+    fn char(self) -> Option<char> {
+        if self.0 & Glyph::CLUSTER == 0 {
+            char::from_u32(self.0 & Glyph::VALUE)
+        } else {
+            None
+        }
+    }
+
+    fn cluster_id(self) -> Option<StrId> {
+        if self.0 & Glyph::CLUSTER != 0 {
+            NonZeroU32::new(self.0 & Glyph::VALUE).map(StrId)
+        } else {
+            None
+        }
+    }
+}
+
+// Text of a cell, as returned by `ScreenBuffer::text`
+pub enum CellText<'a> {
+    Char([u8; 4], usize),
+    Cluster(&'a str),
+}
+
+impl CellText<'_> {
+    fn new(ch: char) -> CellText<'static> {
+        let mut buf = [0; 4];
+        let len = ch.encode_utf8(&mut buf).len();
+        CellText::Char(buf, len)
+    }
+}
+
+impl Deref for CellText<'_> {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        match *self {
+            CellText::Char(ref buf, len) =>
+                str::from_utf8(&buf[..len]).expect("invalid char encoding"),
+            CellText::Cluster(s) => s
+        }
+    }
+}
+
+// Identifies a string in a `Pool`
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+struct StrId(NonZeroU32);
+
+impl StrId {
+    fn index(self) -> usize {
+        self.0.get() as usize - 1
+    }
+
+    fn from_index(idx: usize) -> StrId {
+        StrId(NonZeroU32::new(idx as u32 + 1).expect("pool index overflow"))
+    }
+}
+
+// Stores each distinct string once
+#[derive(Default)]
+struct Pool {
+    strings: Vec<Box<str>>,
+    ids: HashMap<Box<str>, StrId>,
+}
+
+impl Pool {
+    fn len(&self) -> usize {
+        self.strings.len()
+    }
+
+    fn get(&self, id: StrId) -> &str {
+        &self.strings[id.index()]
+    }
+
+    fn find(&self, s: &str) -> Option<StrId> {
+        self.ids.get(s).copied()
+    }
+
+    fn insert(&mut self, s: &str) -> StrId {
+        let id = StrId::from_index(self.strings.len());
+
+        self.strings.push(s.into());
+        self.ids.insert(s.into(), id);
+
+        id
+    }
+
+    // Retains only strings whose index is marked in `used`.
+    // Returns the new identifier of each string, indexed by its old identifier.
+    fn retain(&mut self, used: &[bool]) -> Vec<Option<StrId>> {
+        let strings = mem::take(&mut self.strings);
+        let mut ids = Vec::with_capacity(strings.len());
+
+        self.ids.clear();
+
+        for (s, &used) in strings.into_iter().zip(used) {
+            if used {
+                let id = StrId::from_index(self.strings.len());
+                self.ids.insert(s.clone(), id);
+                self.strings.push(s);
+                ids.push(Some(id));
+            } else {
+                ids.push(None);
+            }
+        }
+
+        ids
     }
 }
 
@@ -526,11 +1030,9 @@ fn resize_buffer(buf: &mut Vec<Cell>, old: Size, new: Size) {
         if !buf.is_empty() {
             let n_cols = old.columns.min(new.columns);
 
-            for (old, new) in buf.chunks_mut(old.columns)
+            for (old, new) in buf.chunks(old.columns)
                     .zip(new_buf.chunks_mut(new.columns)) {
-                for i in 0..n_cols {
-                    swap(&mut new[i], &mut old[i]);
-                }
+                new[..n_cols].copy_from_slice(&old[..n_cols]);
             }
         }
 
@@ -538,6 +1040,47 @@ fn resize_buffer(buf: &mut Vec<Cell>, old: Size, new: Size) {
     }
 }
 
+fn hash_line(line: &[Cell]) -> u64 {
+    let mut h = LineHasher::default();
+    line.hash(&mut h);
+    h.finish()
+}
+
+// Fast, non-cryptographic hasher for lines of cells.
+// Collisions are harmless, as lines are compared before scrolling.
+#[derive(Default)]
+struct LineHasher(u64);
+
+impl LineHasher {
+    fn add(&mut self, n: u64) {
+        self.0 = (self.0.rotate_left(5) ^ n).wrapping_mul(0x517c_c1b7_2722_0a95);
+    }
+}
+
+impl Hasher for LineHasher {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for &b in bytes {
+            self.add(b as u64);
+        }
+    }
+
+    fn write_u8(&mut self, n: u8) { self.add(n as u64); }
+    fn write_u16(&mut self, n: u16) { self.add(n as u64); }
+    fn write_u32(&mut self, n: u32) { self.add(n as u64); }
+    fn write_u64(&mut self, n: u64) { self.add(n); }
+    fn write_usize(&mut self, n: usize) { self.add(n as u64); }
+}
+
+fn fill_default(cells: &mut [Cell]) {
+    for cell in cells {
+        *cell = Cell::default();
+    }
+}
+
 fn new_buffer(buf: &mut Vec<Cell>, new_size: Size) {
     // Invalidate the buffer; all cells will be redrawn
     *buf = vec![Cell::invalid(); new_size.area()];
@@ -545,9 +1088,8 @@ fn new_buffer(buf: &mut Vec<Cell>, new_size: Size) {
 
 #[cfg(test)]
 mod test {
-    use crate::terminal::{Cursor, Size};
-    use crate::util::char_width;
-    use super::ScreenBuffer;
+    use crate::terminal::{Color, Cursor, Size, Style, Theme, UnderlineStyle};
+    use super::{Scroll, ScreenBuffer};
 
     macro_rules! assert_lines {
         ( $buf:expr , $lines:expr ) => {
@@ -567,10 +1109,9 @@ mod test {
 
             while column < size.columns {
                 let cell = buf.cell(Cursor{line, column});
-                text.push_str(&cell.text);
+                text.push_str(&buf.text(cell));
 
-                column += cell.text.chars().next()
-                    .and_then(char_width).unwrap_or(1);
+                column += cell.width();
             }
 
             let next_line = lines[line];
@@ -582,6 +1123,119 @@ mod test {
         }
     }
 
+    // Simulates a refresh, returning the lines of changed cells
+    fn refresh(buf: &mut ScreenBuffer) -> Vec<usize> {
+        let mut indices = buf.indices();
+        let mut lines = Vec::new();
+
+        while let Some((pos, _)) = buf.next_cell(&mut indices) {
+            if lines.last() != Some(&pos.line) {
+                lines.push(pos.line);
+            }
+        }
+
+        lines
+    }
+
+    #[test]
+    fn test_buffer_scroll() {
+        let mut buf = ScreenBuffer::new(Size{lines: 4, columns: 4});
+
+        buf.write_str("aaaabbbbccccdddd").unwrap();
+        assert_eq!(refresh(&mut buf), [0, 1, 2, 3]);
+        assert_eq!(buf.find_scroll(), None);
+
+        buf.write_at((0, 0).into(), "bbbbccccddddeeee").unwrap();
+        let scroll = buf.find_scroll().unwrap();
+        assert_eq!(scroll, Scroll{top: 0, bottom: 4, n: 1});
+
+        buf.apply_scroll(&scroll);
+        assert_eq!(refresh(&mut buf), [3]);
+
+        // The last line remains in place
+        buf.write_at((0, 0).into(), "xxxxbbbbcccc").unwrap();
+        let scroll = buf.find_scroll().unwrap();
+        assert_eq!(scroll, Scroll{top: 0, bottom: 3, n: -1});
+
+        buf.apply_scroll(&scroll);
+        assert_eq!(refresh(&mut buf), [0]);
+        assert_lines!(buf, ["xxxx", "bbbb", "cccc", "eeee"]);
+    }
+
+    #[test]
+    fn test_buffer_scroll_exposed() {
+        let mut buf = ScreenBuffer::new(Size{lines: 6, columns: 2});
+
+        buf.write_str("aabbccddeeff").unwrap();
+        refresh(&mut buf);
+
+        // Scrolling would keep one line, but expose four correct lines
+        buf.write_at((0, 0).into(), "ffxx").unwrap();
+        assert_eq!(buf.find_scroll(), None);
+        assert_eq!(refresh(&mut buf), [0, 1]);
+    }
+
+    #[test]
+    fn test_buffer_dirty() {
+        let mut buf = ScreenBuffer::new(Size{lines: 4, columns: 8});
+        assert!(buf.dirty.iter().all(|d| d.start == d.end));
+
+        buf.write_str("aaaa").unwrap();
+        buf.write_at((1, 6).into(), "zz").unwrap();
+        assert_eq!(buf.dirty[1], 6..8);
+        assert_eq!(refresh(&mut buf), [0, 1]);
+        assert!(buf.dirty.iter().all(|d| d.start == d.end));
+
+        buf.write_at((2, 5).into(), "b").unwrap();
+        buf.write_at((2, 1).into(), "c").unwrap();
+        assert_eq!(buf.dirty[2], 1..6);
+        assert_eq!(buf.dirty[0], 0..0);
+
+        let mut indices = buf.indices();
+        let (pos, _) = buf.next_cell(&mut indices).unwrap();
+        assert_eq!(pos, (2, 1).into());
+        let (pos, _) = buf.next_cell(&mut indices).unwrap();
+        assert_eq!(pos, (2, 5).into());
+        assert!(buf.next_cell(&mut indices).is_none());
+        assert!(buf.dirty.iter().all(|d| d.start == d.end));
+
+        // Unchanged writes are not drawn
+        buf.write_at((0, 0).into(), "aa").unwrap();
+        assert_eq!(buf.dirty[0], 0..2);
+        assert_eq!(refresh(&mut buf), []);
+        assert_eq!(buf.find_scroll(), None);
+
+        // The wide character overlapping a changed cell is drawn
+        buf.write_at((3, 2).into(), "Ｆ").unwrap();
+        assert_eq!(refresh(&mut buf), [3]);
+        buf.dirty[3] = 3..4;
+        buf.back_buffer[3 * 8 + 2] = Default::default();
+        let mut indices = buf.indices();
+        let (pos, cell) = buf.next_cell(&mut indices).unwrap();
+        assert_eq!(pos, (3, 2).into());
+        assert_eq!(&*buf.text(&cell), "Ｆ");
+    }
+
+    #[test]
+    fn test_buffer_run_length() {
+        let mut buf = ScreenBuffer::new(Size{lines: 2, columns: 8});
+
+        buf.write_str("aaabbＦ").unwrap();
+        assert_eq!(buf.run_length((0, 0).into()), 3);
+        assert_eq!(buf.run_length((0, 3).into()), 2);
+        assert_eq!(buf.run_length((0, 5).into()), 1);
+        // Runs do not continue onto the next line
+        assert_eq!(buf.run_length((0, 7).into()), 1);
+
+        let mut indices = buf.indices();
+        buf.next_cell(&mut indices).unwrap();
+        buf.skip_cells(&mut indices, 2);
+
+        let (pos, cell) = buf.next_cell(&mut indices).unwrap();
+        assert_eq!(pos, (0, 3).into());
+        assert_eq!(&*buf.text(&cell), "b");
+    }
+
     #[test]
     fn test_buffer_bounds() {
         let mut buf = ScreenBuffer::new(Size{lines: 1, columns: 1});
@@ -590,6 +1244,51 @@ mod test {
         assert!(buf.write_char('b').is_err());
     }
 
+    #[test]
+    fn test_buffer_underline() {
+        let mut buf = ScreenBuffer::new(Size{lines: 1, columns: 4});
+
+        buf.add_style(Style::UNDERLINE);
+        buf.set_underline_style(UnderlineStyle::Curly);
+        buf.set_underline_color(Some(Color::Red));
+        buf.write_str("ab").unwrap();
+        buf.clear_attributes();
+        buf.write_str("c").unwrap();
+
+        let attrs = buf.cell(Cursor{line: 0, column: 1}).attrs();
+        assert_eq!(attrs.style, Style::UNDERLINE);
+        assert_eq!(attrs.underline, UnderlineStyle::Curly);
+        assert_eq!(attrs.underline_color, Some(Color::Red));
+
+        assert_eq!(buf.cell(Cursor{line: 0, column: 2}).attrs(), Theme::default());
+    }
+
+    #[test]
+    fn test_buffer_link() {
+        let mut buf = ScreenBuffer::new(Size{lines: 1, columns: 4});
+
+        buf.set_link(Some("https://example.com/"));
+        buf.write_str("ab").unwrap();
+        buf.set_link(None);
+        buf.write_str("c").unwrap();
+
+        assert_eq!(buf.link(buf.cell(Cursor{line: 0, column: 1})),
+            Some("https://example.com/"));
+        assert_eq!(buf.link(buf.cell(Cursor{line: 0, column: 2})), None);
+
+        // Links are not affected by other attributes
+        buf.set_link(Some("https://example.com/"));
+        buf.clear_attributes();
+        buf.write_str("d").unwrap();
+
+        assert_eq!(buf.link(buf.cell(Cursor{line: 0, column: 3})),
+            Some("https://example.com/"));
+
+        // Equal links are pooled once
+        assert_eq!(buf.cell(Cursor{line: 0, column: 1}).link,
+            buf.cell(Cursor{line: 0, column: 3}).link);
+    }
+
     #[test]
     fn test_buffer_combining() {
         let mut buf = ScreenBuffer::new(Size{lines: 1, columns: 1});
@@ -600,6 +1299,35 @@ mod test {
         assert!(buf.write_str("x").is_err())
     }
 
+    #[test]
+    fn test_buffer_pool() {
+        let mut buf = ScreenBuffer::new(Size{lines: 2, columns: 4});
+
+        buf.write_str("e\u{301}Ｆ").unwrap();
+        buf.write_at((1, 0).into(), "e\u{301}").unwrap();
+        assert_lines!(buf, ["e\u{301}Ｆ", "e\u{301}"]);
+        assert_eq!(buf.cell((0, 0).into()), buf.cell((1, 0).into()));
+        assert_eq!(buf.cell((0, 1).into()).width(), 2);
+        assert_eq!(buf.pool.len(), 1);
+
+        // Strings no longer in use are collected
+        for i in 0..1000 {
+            let ch = char::from_u32('a' as u32 + i % 26).unwrap();
+            buf.write_at((1, 1).into(), &format!("{}\u{301}", ch)).unwrap();
+            refresh(&mut buf);
+        }
+
+        assert!(buf.pool.len() <= super::MIN_POOL_LIMIT);
+        assert_lines!(buf, ["e\u{301}Ｆ", "e\u{301}l\u{301}"]);
+        assert_eq!(buf.cell((0, 0).into()), buf.cell((1, 0).into()));
+        assert_eq!(buf.cell((0, 1).into()).width(), 2);
+    }
+
+    #[test]
+    fn test_cell_size() {
+        assert!(std::mem::size_of::<super::Cell>() <= 24);
+    }
+
     #[test]
     fn test_buffer_tab() {
         let mut buf = ScreenBuffer::new(Size{lines: 2, columns: 10});
@@ -45,12 +45,14 @@ pub use crate::screen::{Screen, ScreenReadGuard, ScreenWriteGuard};
 pub use crate::sequence::{FindResult, SequenceMap};
 pub use crate::signal::{Signal, SignalSet};
 pub use crate::terminal::{
-    Color, Cursor, CursorMode, Size, Style, Theme,
-    Event, Key, MouseEvent, MouseInput, MouseButton, ModifierState,
+    Color, ColorDepth, Cursor, CursorMode, Size, Style, Theme, UnderlineStyle,
+    Event, Key, KeyEvent, KeyEventKind, MouseEvent, MouseInput, MouseButton, ModifierState,
+    PixelPosition, PixelSize, Query, Reply, ModeStatus, Selection,
     PrepareConfig, PrepareState,
     Terminal, TerminalReadGuard, TerminalWriteGuard,
 };
 
+#[cfg_attr(feature = "bench", allow(missing_docs))]
 #[macro_use] mod buffer;
 #[doc(hidden)]
 #[macro_use] pub mod macros;
@@ -75,6 +77,11 @@ pub use crate::sys::ext as unix;
 #[cfg(windows)]
 pub use sys::ext as windows;
 
+// Used by benchmarks; not part of the public API.
+#[cfg(feature = "bench")]
+#[doc(hidden)]
+pub use crate::buffer::ScreenBuffer;
+
 #[cfg(test)]
 mod test {
     use crate::screen::Screen;
@@ -18,18 +18,22 @@ use std::io;
 /// | ----------------- | --------------------------------- |
 /// | `[red]`           | `term.set_fg(Color::Red)`         |
 /// | `[#blue]`         | `term.set_bg(Color::Blue)`        |
+/// | `[bright_red]`    | `term.set_fg(Color::BrightRed)`   |
+/// | `[#bright_blue]`  | `term.set_bg(Color::BrightBlue)`  |
 /// | `[bold]`          | `term.add_style(Style::BOLD)`     |
 /// | `[!bold]`         | `term.remove_style(Style::BOLD)`  |
 /// | `[reset]`         | `term.clear_attributes()`         |
 /// | `[!fg]`           | `term.set_fg(None)`               |
 /// | `[!bg]`           | `term.set_bg(None)`               |
 /// | `[!style]`        | `term.set_style(None)`            |
+/// | `[!link]`         | `term.set_link(None)`             |
 /// | `[fg=expr]`       | `term.set_fg(expr)`               |
 /// | `[bg=expr]`       | `term.set_bg(expr)`               |
 /// | `[style=expr]`    | `term.set_style(expr)`            |
 /// | `[style+=expr]`   | `term.add_style(expr)`            |
 /// | `[style-=expr]`   | `term.remove_style(expr)`         |
 /// | `[theme=expr]`    | `term.set_theme(expr)`            |
+/// | `[link=expr]`     | `term.set_link(expr)`             |
 ///
 /// Formatted text elements are enclosed in parentheses
 /// and use Rust [`std::fmt`] functions to write formatted text to the terminal.
@@ -58,6 +62,8 @@ use std::io;
 /// let color = Color::Green;
 /// term_writeln!(term, [fg=color] "green text" [reset])?;
 ///
+/// term_writeln!(term, [fg=Color::Rgb(255, 128, 0)] "orange text" [reset])?;
+///
 /// let style = Style::BOLD;
 /// term_writeln!(term, [style=style] "bold text" [reset])?;
 ///
@@ -66,6 +72,9 @@ use std::io;
 ///
 /// let theme = Theme::new(color, None, style);
 /// term_writeln!(term, [theme=theme] "Green, bold text" [reset])?;
+///
+/// let url = "https://example.com/";
+/// term_writeln!(term, "See " [link=url] (: url) [!link] " for details")?;
 /// # Ok(())
 /// # }
 /// ```
@@ -147,6 +156,38 @@ macro_rules! term_write {
         $crate::macros::Chain::chain(
             $result, || $term.set_fg($crate::Color::Yellow))
     };
+    ( @_INTERNAL style: $term:expr ; $result:expr ; bright_black ) => {
+        $crate::macros::Chain::chain(
+            $result, || $term.set_fg($crate::Color::BrightBlack))
+    };
+    ( @_INTERNAL style: $term:expr ; $result:expr ; bright_blue ) => {
+        $crate::macros::Chain::chain(
+            $result, || $term.set_fg($crate::Color::BrightBlue))
+    };
+    ( @_INTERNAL style: $term:expr ; $result:expr ; bright_cyan ) => {
+        $crate::macros::Chain::chain(
+            $result, || $term.set_fg($crate::Color::BrightCyan))
+    };
+    ( @_INTERNAL style: $term:expr ; $result:expr ; bright_green ) => {
+        $crate::macros::Chain::chain(
+            $result, || $term.set_fg($crate::Color::BrightGreen))
+    };
+    ( @_INTERNAL style: $term:expr ; $result:expr ; bright_magenta ) => {
+        $crate::macros::Chain::chain(
+            $result, || $term.set_fg($crate::Color::BrightMagenta))
+    };
+    ( @_INTERNAL style: $term:expr ; $result:expr ; bright_red ) => {
+        $crate::macros::Chain::chain(
+            $result, || $term.set_fg($crate::Color::BrightRed))
+    };
+    ( @_INTERNAL style: $term:expr ; $result:expr ; bright_white ) => {
+        $crate::macros::Chain::chain(
+            $result, || $term.set_fg($crate::Color::BrightWhite))
+    };
+    ( @_INTERNAL style: $term:expr ; $result:expr ; bright_yellow ) => {
+        $crate::macros::Chain::chain(
+            $result, || $term.set_fg($crate::Color::BrightYellow))
+    };
 
     // Set background color
     ( @_INTERNAL style: $term:expr ; $result:expr ; # black ) => {
@@ -181,6 +222,38 @@ macro_rules! term_write {
         $crate::macros::Chain::chain(
             $result, || $term.set_bg($crate::Color::Yellow))
     };
+    ( @_INTERNAL style: $term:expr ; $result:expr ; # bright_black ) => {
+        $crate::macros::Chain::chain(
+            $result, || $term.set_bg($crate::Color::BrightBlack))
+    };
+    ( @_INTERNAL style: $term:expr ; $result:expr ; # bright_blue ) => {
+        $crate::macros::Chain::chain(
+            $result, || $term.set_bg($crate::Color::BrightBlue))
+    };
+    ( @_INTERNAL style: $term:expr ; $result:expr ; # bright_cyan ) => {
+        $crate::macros::Chain::chain(
+            $result, || $term.set_bg($crate::Color::BrightCyan))
+    };
+    ( @_INTERNAL style: $term:expr ; $result:expr ; # bright_green ) => {
+        $crate::macros::Chain::chain(
+            $result, || $term.set_bg($crate::Color::BrightGreen))
+    };
+    ( @_INTERNAL style: $term:expr ; $result:expr ; # bright_magenta ) => {
+        $crate::macros::Chain::chain(
+            $result, || $term.set_bg($crate::Color::BrightMagenta))
+    };
+    ( @_INTERNAL style: $term:expr ; $result:expr ; # bright_red ) => {
+        $crate::macros::Chain::chain(
+            $result, || $term.set_bg($crate::Color::BrightRed))
+    };
+    ( @_INTERNAL style: $term:expr ; $result:expr ; # bright_white ) => {
+        $crate::macros::Chain::chain(
+            $result, || $term.set_bg($crate::Color::BrightWhite))
+    };
+    ( @_INTERNAL style: $term:expr ; $result:expr ; # bright_yellow ) => {
+        $crate::macros::Chain::chain(
+            $result, || $term.set_bg($crate::Color::BrightYellow))
+    };
 
     // Add style
     ( @_INTERNAL style: $term:expr ; $result:expr ; bold ) => {
@@ -199,6 +272,26 @@ macro_rules! term_write {
         $crate::macros::Chain::chain(
             $result, || $term.add_style($crate::Style::UNDERLINE))
     };
+    ( @_INTERNAL style: $term:expr ; $result:expr ; dim ) => {
+        $crate::macros::Chain::chain(
+            $result, || $term.add_style($crate::Style::DIM))
+    };
+    ( @_INTERNAL style: $term:expr ; $result:expr ; blink ) => {
+        $crate::macros::Chain::chain(
+            $result, || $term.add_style($crate::Style::BLINK))
+    };
+    ( @_INTERNAL style: $term:expr ; $result:expr ; strikethrough ) => {
+        $crate::macros::Chain::chain(
+            $result, || $term.add_style($crate::Style::STRIKETHROUGH))
+    };
+    ( @_INTERNAL style: $term:expr ; $result:expr ; hidden ) => {
+        $crate::macros::Chain::chain(
+            $result, || $term.add_style($crate::Style::HIDDEN))
+    };
+    ( @_INTERNAL style: $term:expr ; $result:expr ; overline ) => {
+        $crate::macros::Chain::chain(
+            $result, || $term.add_style($crate::Style::OVERLINE))
+    };
 
     // Remove style
     ( @_INTERNAL style: $term:expr ; $result:expr ; ! bold ) => {
@@ -217,6 +310,26 @@ macro_rules! term_write {
         $crate::macros::Chain::chain(
             $result, || $term.remove_style($crate::Style::UNDERLINE))
     };
+    ( @_INTERNAL style: $term:expr ; $result:expr ; ! dim ) => {
+        $crate::macros::Chain::chain(
+            $result, || $term.remove_style($crate::Style::DIM))
+    };
+    ( @_INTERNAL style: $term:expr ; $result:expr ; ! blink ) => {
+        $crate::macros::Chain::chain(
+            $result, || $term.remove_style($crate::Style::BLINK))
+    };
+    ( @_INTERNAL style: $term:expr ; $result:expr ; ! strikethrough ) => {
+        $crate::macros::Chain::chain(
+            $result, || $term.remove_style($crate::Style::STRIKETHROUGH))
+    };
+    ( @_INTERNAL style: $term:expr ; $result:expr ; ! hidden ) => {
+        $crate::macros::Chain::chain(
+            $result, || $term.remove_style($crate::Style::HIDDEN))
+    };
+    ( @_INTERNAL style: $term:expr ; $result:expr ; ! overline ) => {
+        $crate::macros::Chain::chain(
+            $result, || $term.remove_style($crate::Style::OVERLINE))
+    };
 
     // Clear attributes
     ( @_INTERNAL style: $term:expr ; $result:expr ; reset ) => {
@@ -235,6 +348,10 @@ macro_rules! term_write {
         $crate::macros::Chain::chain(
             $result, || $term.set_style(None))
     };
+    ( @_INTERNAL style: $term:expr ; $result:expr ; ! link ) => {
+        $crate::macros::Chain::chain(
+            $result, || $term.set_link(None))
+    };
 
     // Color/style expressions
     ( @_INTERNAL style: $term:expr ; $result:expr ; fg = $e:expr ) => {
@@ -261,6 +378,10 @@ macro_rules! term_write {
         $crate::macros::Chain::chain(
             $result, || $term.set_theme($e))
     };
+    ( @_INTERNAL style: $term:expr ; $result:expr ; link = $e:expr ) => {
+        $crate::macros::Chain::chain(
+            $result, || $term.set_link($e))
+    };
 
     // std::fmt formatting
     ( @_INTERNAL format: $term:expr ; $result:expr ; : $e:expr ) => {
@@ -1,7 +1,8 @@
 use std::sync::{LockResult, PoisonError, TryLockError, TryLockResult};
+use std::time::{Duration, Instant};
 
 use crate::screen::{Screen, ScreenReadGuard};
-use crate::terminal::{Terminal, TerminalReadGuard};
+use crate::terminal::{Cursor, MouseButton, Terminal, TerminalReadGuard};
 use crate::util::char_width;
 
 // Private trait used to prevent external crates from implementing extension traits
@@ -12,6 +13,45 @@ impl<'a> Private for ScreenReadGuard<'a> {}
 impl Private for Terminal {}
 impl<'a> Private for TerminalReadGuard<'a> {}
 
+// Counts successive clicks of a mouse button, for reporting double clicks
+#[derive(Default)]
+pub struct ClickCounter {
+    last: Option<(MouseButton, Cursor, Instant)>,
+    count: u32,
+}
+
+impl ClickCounter {
+    pub fn new() -> ClickCounter {
+        ClickCounter::default()
+    }
+
+    // Records a button press and returns the number of consecutive clicks
+    pub fn click(&mut self, button: MouseButton, position: Cursor,
+            interval: Option<Duration>) -> u32 {
+        self.click_at(button, position, interval, Instant::now())
+    }
+
+    fn click_at(&mut self, button: MouseButton, position: Cursor,
+            interval: Option<Duration>, now: Instant) -> u32 {
+        let interval = match interval {
+            Some(interval) => interval,
+            None => return 1
+        };
+
+        match self.last {
+            Some((last_button, last_pos, last_time))
+                    if last_button == button && last_pos == position &&
+                        now.duration_since(last_time) <= interval => {
+                self.count += 1;
+            }
+            _ => self.count = 1
+        }
+
+        self.last = Some((button, position, now));
+        self.count
+    }
+}
+
 pub fn is_visible(ch: char) -> bool {
     match ch {
         '\t' | '\r' | '\n' => true,
@@ -63,3 +103,29 @@ pub fn map2_try_lock_result<F, T, U, R>(
                 f(a.into_inner(), b.into_inner())))),
     }
 }
+
+#[cfg(test)]
+mod test {
+    use std::time::{Duration, Instant};
+
+    use crate::terminal::{Cursor, MouseButton};
+    use super::ClickCounter;
+
+    #[test]
+    fn test_click_counter() {
+        let mut clicks = ClickCounter::new();
+        let interval = Some(Duration::from_millis(500));
+        let pos = Cursor{line: 1, column: 2};
+        let start = Instant::now();
+        let ms = |n| start + Duration::from_millis(n);
+
+        assert_eq!(clicks.click_at(MouseButton::Left, pos, interval, ms(0)), 1);
+        assert_eq!(clicks.click_at(MouseButton::Left, pos, interval, ms(300)), 2);
+        assert_eq!(clicks.click_at(MouseButton::Left, pos, interval, ms(600)), 3);
+        assert_eq!(clicks.click_at(MouseButton::Left, pos, interval, ms(1200)), 1);
+        assert_eq!(clicks.click_at(MouseButton::Right, pos, interval, ms(1300)), 1);
+        assert_eq!(clicks.click_at(MouseButton::Right, Cursor{line: 1, column: 3},
+            interval, ms(1400)), 1);
+        assert_eq!(clicks.click_at(MouseButton::Right, pos, None, ms(1500)), 1);
+    }
+}
@@ -8,8 +8,8 @@ use std::time::Duration;
 use crate::priv_util::{map_lock_result, map_try_lock_result};
 use crate::sys;
 use crate::terminal::{
-    Color, Cursor, CursorMode, Event, PrepareConfig, Size, Style, Theme,
-    Terminal,
+    Color, ColorDepth, Cursor, CursorMode, Event, PixelSize, PrepareConfig,
+    Selection, Size, Style, Theme, Terminal, UnderlineStyle,
 };
 
 /// Provides operations on an underlying terminal device in screen mode.
@@ -75,6 +75,24 @@ impl Screen {
         self.0.name()
     }
 
+    /// Returns the color depth supported by the terminal.
+    ///
+    /// Colors which cannot be displayed by the terminal are replaced
+    /// with the nearest supported color.
+    ///
+    /// # Notes
+    ///
+    /// On Unix, support for direct (24-bit) color is detected using the
+    /// `COLORTERM` environment variable and the `RGB` or `Tc` terminfo
+    /// capabilities. Otherwise, the color depth is determined by the
+    /// `colors` terminfo capability.
+    ///
+    /// On Windows, this method always returns `ColorDepth::Color16`.
+    #[inline]
+    pub fn color_depth(&self) -> ColorDepth {
+        self.0.color_depth()
+    }
+
     /// Attempts to acquire an exclusive lock on terminal read operations.
     ///
     /// The current thread will block until the lock can be acquired.
@@ -145,6 +163,45 @@ impl Screen {
     }
 }
 
+/// # Locking
+///
+/// The following methods internally acquire both the read and write locks.
+///
+/// The locks are released before the method returns.
+impl Screen {
+    /// Returns the size of the terminal window in pixels.
+    ///
+    /// If the size is not reported by the operating system,
+    /// the terminal is queried, waiting briefly for a response.
+    ///
+    /// Returns `Ok(None)` if the size cannot be determined.
+    pub fn pixel_size(&self) -> io::Result<Option<PixelSize>> {
+        self.0.pixel_size()
+    }
+
+    /// Returns the size of a single character cell in pixels.
+    ///
+    /// If the size is not reported by the operating system,
+    /// the terminal is queried, waiting briefly for a response.
+    ///
+    /// Returns `Ok(None)` if the size cannot be determined.
+    pub fn cell_size(&self) -> io::Result<Option<PixelSize>> {
+        self.0.cell_size()
+    }
+
+    /// Returns the background color of the terminal,
+    /// as `Color::Rgb(_, _, _)`.
+    ///
+    /// Returns `Ok(None)` if the background color cannot be determined.
+    ///
+    /// See [`Terminal::background_color`] for details.
+    ///
+    /// [`Terminal::background_color`]: ../terminal/struct.Terminal.html#method.background_color
+    pub fn background_color(&self) -> io::Result<Option<Color>> {
+        self.0.background_color()
+    }
+}
+
 /// # Locking
 ///
 /// The following methods internally acquire the write lock.
@@ -192,6 +249,29 @@ impl Screen {
         self.0.set_cursor_mode(mode)
     }
 
+    /// Sets the title of the terminal window.
+    ///
+    /// The original title is restored when the `Screen` is dropped.
+    ///
+    /// # Notes
+    ///
+    /// On some systems, this setting may have no effect.
+    pub fn set_title(&self, title: &str) -> io::Result<()> {
+        self.0.set_title(title)
+    }
+
+    /// Sets the contents of a selection buffer, such as the system clipboard.
+    ///
+    /// The text is sent to the terminal, rather than stored locally,
+    /// which allows it to reach the user's clipboard through a remote session.
+    ///
+    /// # Notes
+    ///
+    /// On some systems, this setting may have no effect.
+    pub fn set_clipboard(&self, selection: Selection, text: &str) -> io::Result<()> {
+        self.0.set_clipboard(selection, text)
+    }
+
     /// Clears the internal screen buffer.
     pub fn clear_screen(&self) {
         self.0.clear_screen();
@@ -227,6 +307,30 @@ impl Screen {
         self.0.set_bg(bg.into());
     }
 
+    /// Sets the style of underlined text.
+    #[inline]
+    pub fn set_underline_style(&self, style: UnderlineStyle) {
+        self.0.set_underline_style(style);
+    }
+
+    /// Sets or removes the color of underlined text.
+    ///
+    /// If `None`, underlines are displayed in the foreground color.
+    #[inline]
+    pub fn set_underline_color<C: Into<Option<Color>>>(&self, color: C) {
+        self.0.set_underline_color(color.into());
+    }
+
+    /// Sets or removes the hyperlink applied to subsequently written text.
+    ///
+    /// # Notes
+    ///
+    /// On some systems, links may not be displayed.
+    #[inline]
+    pub fn set_link<'b, L: Into<Option<&'b str>>>(&self, uri: L) {
+        self.0.set_link(uri.into());
+    }
+
     /// Sets all attributes for the screen.
     #[inline]
     pub fn set_theme(&self, theme: Theme) {
@@ -424,6 +528,29 @@ impl<'a> ScreenWriteGuard<'a> {
         self.0.set_cursor_mode(mode)
     }
 
+    /// Sets the title of the terminal window.
+    ///
+    /// The original title is restored when the `Screen` is dropped.
+    ///
+    /// # Notes
+    ///
+    /// On some systems, this setting may have no effect.
+    pub fn set_title(&mut self, title: &str) -> io::Result<()> {
+        self.0.set_title(title)
+    }
+
+    /// Sets the contents of a selection buffer, such as the system clipboard.
+    ///
+    /// The text is sent to the terminal, rather than stored locally,
+    /// which allows it to reach the user's clipboard through a remote session.
+    ///
+    /// # Notes
+    ///
+    /// On some systems, this setting may have no effect.
+    pub fn set_clipboard(&mut self, selection: Selection, text: &str) -> io::Result<()> {
+        self.0.set_clipboard(selection, text)
+    }
+
     /// Adds a set of `Style` flags to the current style setting.
     pub fn clear_screen(&mut self) {
         self.0.clear_screen();
@@ -460,6 +587,30 @@ impl<'a> ScreenWriteGuard<'a> {
         self.0.set_bg(bg.into())
     }
 
+    /// Sets the style of underlined text.
+    #[inline]
+    pub fn set_underline_style(&mut self, style: UnderlineStyle) {
+        self.0.set_underline_style(style)
+    }
+
+    /// Sets or removes the color of underlined text.
+    ///
+    /// If `None`, underlines are displayed in the foreground color.
+    #[inline]
+    pub fn set_underline_color<C: Into<Option<Color>>>(&mut self, color: C) {
+        self.0.set_underline_color(color.into())
+    }
+
+    /// Sets or removes the hyperlink applied to subsequently written text.
+    ///
+    /// # Notes
+    ///
+    /// On some systems, links may not be displayed.
+    #[inline]
+    pub fn set_link<'b, L: Into<Option<&'b str>>>(&mut self, uri: L) {
+        self.0.set_link(uri.into())
+    }
+
     /// Sets all attributes for the screen.
     #[inline]
     pub fn set_theme(&mut self, theme: Theme) {
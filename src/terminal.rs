@@ -16,7 +16,15 @@ use crate::sys;
 /// Names here correspond to possible default values for some systems.
 /// Because users may reconfigure the set of colors available in their terminal,
 /// these color values may correspond to different user-configured display colors.
-#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+///
+/// Bright, `Indexed`, and `Rgb` colors may not be supported on all systems.
+/// Colors written to a [`Terminal`] or [`Screen`] are automatically replaced
+/// with the nearest color supported by the terminal; see [`ColorDepth`].
+///
+/// [`ColorDepth`]: enum.ColorDepth.html
+/// [`Screen`]: ../screen/struct.Screen.html
+/// [`Terminal`]: struct.Terminal.html
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
 pub enum Color {
     /// Black
     Black,
@@ -34,6 +42,221 @@ pub enum Color {
     White,
     /// Yellow
     Yellow,
+    /// Bright black; commonly displayed as dark gray
+    BrightBlack,
+    /// Bright blue
+    BrightBlue,
+    /// Bright cyan
+    BrightCyan,
+    /// Bright green
+    BrightGreen,
+    /// Bright magenta
+    BrightMagenta,
+    /// Bright red
+    BrightRed,
+    /// Bright white
+    BrightWhite,
+    /// Bright yellow
+    BrightYellow,
+    /// Color at the given index in the terminal's 256-color palette
+    ///
+    /// Indices `0` through `15` refer to the basic and bright colors,
+    /// `16` through `231` to a 6x6x6 color cube,
+    /// and `232` through `255` to a grayscale ramp.
+    Indexed(u8),
+    /// 24-bit color, given as red, green, and blue components
+    Rgb(u8, u8, u8),
+}
+
+impl Color {
+    /// Returns the nearest color which can be displayed at the given depth.
+    ///
+    /// At `Color16` depth, the result is always a basic or bright color variant;
+    /// at `Color8` depth, the result is always a basic color variant.
+    ///
+    /// # Notes
+    ///
+    /// Because terminal palettes may be reconfigured by the user,
+    /// the distance between colors is approximated using the default
+    /// palette of `xterm`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mortal::{Color, ColorDepth};
+    /// let salmon = Color::Rgb(255, 80, 80);
+    ///
+    /// assert_eq!(salmon.quantize(ColorDepth::TrueColor), salmon);
+    /// assert_eq!(salmon.quantize(ColorDepth::Color256), Color::Indexed(203));
+    /// assert_eq!(salmon.quantize(ColorDepth::Color16), Color::BrightRed);
+    /// assert_eq!(salmon.quantize(ColorDepth::Color8), Color::Red);
+    /// ```
+    pub fn quantize(self, depth: ColorDepth) -> Color {
+        let n_colors = match depth {
+            ColorDepth::TrueColor => return self,
+            ColorDepth::Color256 => {
+                return match self {
+                    Color::Rgb(r, g, b) => Color::Indexed(nearest_indexed(r, g, b)),
+                    _ => self
+                };
+            }
+            ColorDepth::Color16 => 16,
+            ColorDepth::Color8 => 8,
+        };
+
+        match self.ansi_index() {
+            // Bright colors map to the corresponding basic color
+            Some(n) => ANSI_COLORS[n % n_colors],
+            None => {
+                let rgb = self.to_rgb();
+
+                ANSI_COLORS[..n_colors].iter().cloned()
+                    .min_by_key(|c| color_distance(rgb, c.to_rgb()))
+                    .unwrap()
+            }
+        }
+    }
+
+    /// Returns whether the color is light, i.e. whether dark text
+    /// would be more legible than light text against it.
+    ///
+    /// This may be used with [`Terminal::background_color`] to select
+    /// a color scheme suited to the terminal.
+    ///
+    /// # Notes
+    ///
+    /// The components of colors other than `Color::Rgb(_, _, _)` are
+    /// approximated using the default palette of `xterm`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mortal::Color;
+    /// assert!(Color::Rgb(0xfd, 0xf6, 0xe3).is_light());
+    /// assert!(!Color::Rgb(0x00, 0x2b, 0x36).is_light());
+    /// assert!(Color::BrightWhite.is_light());
+    /// assert!(!Color::Blue.is_light());
+    /// ```
+    ///
+    /// [`Terminal::background_color`]: struct.Terminal.html#method.background_color
+    pub fn is_light(self) -> bool {
+        // Relative luminance at which black and white text
+        // have equal contrast against a color
+        const THRESHOLD: f64 = 0.179;
+
+        fn linear(v: u8) -> f64 {
+            let v = v as f64 / 255.0;
+
+            if v <= 0.04045 {
+                v / 12.92
+            } else {
+                ((v + 0.055) / 1.055).powf(2.4)
+            }
+        }
+
+        let (r, g, b) = self.to_rgb();
+
+        0.2126 * linear(r) + 0.7152 * linear(g) + 0.0722 * linear(b) > THRESHOLD
+    }
+
+    /// Returns the approximate red, green, and blue components of the color.
+    pub(crate) fn to_rgb(self) -> (u8, u8, u8) {
+        if let Some(n) = self.ansi_index() {
+            return ANSI_RGB[n];
+        }
+
+        match self {
+            Color::Indexed(n @ 16 ..= 231) => {
+                let n = n - 16;
+                (CUBE_LEVELS[(n / 36) as usize],
+                    CUBE_LEVELS[(n / 6 % 6) as usize],
+                    CUBE_LEVELS[(n % 6) as usize])
+            }
+            Color::Indexed(n) => {
+                let v = 8 + (n - 232) * 10;
+                (v, v, v)
+            }
+            Color::Rgb(r, g, b) => (r, g, b),
+            _ => unreachable!()
+        }
+    }
+
+    // Returns the palette index of a basic or bright color
+    fn ansi_index(self) -> Option<usize> {
+        match self {
+            Color::Indexed(n) if n < 16 => Some(n as usize),
+            Color::Indexed(_) | Color::Rgb(..) => None,
+            _ => ANSI_COLORS.iter().position(|&c| c == self)
+        }
+    }
+}
+
+/// Represents the range of colors which a terminal is able to display
+///
+/// Depths are ordered from least to greatest range of colors.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub enum ColorDepth {
+    /// Basic colors only
+    Color8,
+    /// Basic and bright colors
+    Color16,
+    /// 256-color palette
+    Color256,
+    /// 24-bit color
+    TrueColor,
+}
+
+// Basic and bright colors, in palette order
+const ANSI_COLORS: [Color; 16] = [
+    Color::Black, Color::Red, Color::Green, Color::Yellow,
+    Color::Blue, Color::Magenta, Color::Cyan, Color::White,
+    Color::BrightBlack, Color::BrightRed, Color::BrightGreen, Color::BrightYellow,
+    Color::BrightBlue, Color::BrightMagenta, Color::BrightCyan, Color::BrightWhite,
+];
+
+// Default xterm values for basic and bright colors
+const ANSI_RGB: [(u8, u8, u8); 16] = [
+    (0x00, 0x00, 0x00), (0xcd, 0x00, 0x00), (0x00, 0xcd, 0x00), (0xcd, 0xcd, 0x00),
+    (0x00, 0x00, 0xee), (0xcd, 0x00, 0xcd), (0x00, 0xcd, 0xcd), (0xe5, 0xe5, 0xe5),
+    (0x7f, 0x7f, 0x7f), (0xff, 0x00, 0x00), (0x00, 0xff, 0x00), (0xff, 0xff, 0x00),
+    (0x5c, 0x5c, 0xff), (0xff, 0x00, 0xff), (0x00, 0xff, 0xff), (0xff, 0xff, 0xff),
+];
+
+// Component values of the 256-color palette color cube
+const CUBE_LEVELS: [u8; 6] = [0x00, 0x5f, 0x87, 0xaf, 0xd7, 0xff];
+
+// Returns the index of the nearest color cube or grayscale color
+fn nearest_indexed(r: u8, g: u8, b: u8) -> u8 {
+    fn cube_level(v: u8) -> u8 {
+        match v {
+            0 ..= 47 => 0,
+            48 ..= 114 => 1,
+            _ => (v - 35) / 40,
+        }
+    }
+
+    let cube = 16 + 36 * cube_level(r) + 6 * cube_level(g) + cube_level(b);
+
+    let avg = ((r as u32 + g as u32 + b as u32) / 3) as u8;
+    let gray = 232 + (avg.saturating_sub(3) / 10).min(23);
+
+    let rgb = (r, g, b);
+
+    if color_distance(rgb, Color::Indexed(gray).to_rgb()) <
+            color_distance(rgb, Color::Indexed(cube).to_rgb()) {
+        gray
+    } else {
+        cube
+    }
+}
+
+// Returns the weighted square distance between two colors
+fn color_distance(a: (u8, u8, u8), b: (u8, u8, u8)) -> u32 {
+    let dr = a.0 as i32 - b.0 as i32;
+    let dg = a.1 as i32 - b.1 as i32;
+    let db = a.2 as i32 - b.2 as i32;
+
+    (2 * dr * dr + 4 * dg * dg + 3 * db * db) as u32
 }
 
 bitflags!{
@@ -41,22 +264,56 @@ bitflags!{
     ///
     /// Some styles may not be supported on all systems.
     #[derive(Copy, Clone, Debug, Default, Eq, PartialEq, Hash)]
-    pub struct Style: u8 {
+    pub struct Style: u16 {
         /// Bold
-        const BOLD      = 1 << 0;
+        const BOLD          = 1 << 0;
         /// Italic
-        const ITALIC    = 1 << 1;
+        const ITALIC        = 1 << 1;
         /// Reverse; foreground and background color swapped
-        const REVERSE   = 1 << 2;
+        const REVERSE       = 1 << 2;
         /// Underline
-        const UNDERLINE = 1 << 3;
+        const UNDERLINE     = 1 << 3;
+        /// Dim; also known as faint
+        const DIM           = 1 << 4;
+        /// Blink
+        const BLINK         = 1 << 5;
+        /// Strikethrough; also known as crossed-out
+        const STRIKETHROUGH = 1 << 6;
+        /// Hidden; text is not visible
+        const HIDDEN        = 1 << 7;
+        /// Overline
+        const OVERLINE      = 1 << 8;
     }
 }
 
+/// Represents the style of underlined text.
+///
+/// Underline styles other than `Single` may not be supported on all systems.
+/// Where unsupported, a single underline is displayed instead.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq, Hash)]
+pub enum UnderlineStyle {
+    /// Single underline
+    #[default]
+    Single,
+    /// Double underline
+    Double,
+    /// Curly underline; also known as undercurl
+    Curly,
+    /// Dotted underline
+    Dotted,
+    /// Dashed underline
+    Dashed,
+}
+
 /// Represents a terminal output theme.
 ///
 /// A theme consists of a foreground and background color as well as a style.
-#[derive(Copy, Clone, Debug, Default)]
+/// Underlined text may additionally be given an underline style and color.
+///
+/// Fields may be added in future versions; a `Theme` is created using
+/// `Theme::new` or `Theme::default()` and modified using builder methods.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq, Hash)]
+#[non_exhaustive]
 pub struct Theme {
     /// Foreground color
     pub fg: Option<Color>,
@@ -64,6 +321,10 @@ pub struct Theme {
     pub bg: Option<Color>,
     /// Style
     pub style: Style,
+    /// Underline style; applies only when `style` contains `Style::UNDERLINE`
+    pub underline: UnderlineStyle,
+    /// Underline color; if `None`, the foreground color is used
+    pub underline_color: Option<Color>,
 }
 
 impl Theme {
@@ -82,6 +343,8 @@ impl Theme {
             fg: fg.into(),
             bg: bg.into(),
             style: style.into().unwrap_or_default(),
+            underline: UnderlineStyle::Single,
+            underline_color: None,
         }
     }
 
@@ -105,6 +368,19 @@ impl Theme {
         self.style = style.into().unwrap_or_default();
         self
     }
+
+    /// Sets the underline style on the given Theme and returns the new.
+    pub fn underline(mut self, underline: UnderlineStyle) -> Theme {
+        self.underline = underline;
+        self
+    }
+
+    /// Sets the underline color on the given Theme and returns the new.
+    pub fn underline_color<C>(mut self, color: C) -> Theme
+            where C: Into<Option<Color>> {
+        self.underline_color = color.into();
+        self
+    }
 }
 
 /// Represents the cursor position in a terminal device
@@ -205,12 +481,40 @@ pub enum CursorMode {
 }
 
 /// Represents an event generated from a terminal interface
-#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+#[derive(Clone, Debug, Eq, PartialEq)]
 pub enum Event {
     /// Keyboard event
     Key(Key),
+    /// Keyboard event with modifier keys held, or a key repeat or release
+    ///
+    /// Keys pressed without modifiers are reported as `Key(_)`.
+    KeyMod(KeyEvent),
     /// Mouse event
     Mouse(MouseEvent),
+    /// Text pasted into the terminal
+    ///
+    /// This event is only reported if `enable_bracketed_paste` is set
+    /// in [`PrepareConfig`]. Text is reported as it was received;
+    /// line breaks are typically represented as carriage return (`'\r'`).
+    ///
+    /// Very long pasted text may be reported in a series of `Paste` events.
+    ///
+    /// [`PrepareConfig`]: struct.PrepareConfig.html
+    Paste(String),
+    /// Terminal window gained input focus
+    ///
+    /// This event is only reported if `enable_focus_events` is set
+    /// in [`PrepareConfig`].
+    ///
+    /// [`PrepareConfig`]: struct.PrepareConfig.html
+    FocusGained,
+    /// Terminal window lost input focus
+    ///
+    /// This event is only reported if `enable_focus_events` is set
+    /// in [`PrepareConfig`].
+    ///
+    /// [`PrepareConfig`]: struct.PrepareConfig.html
+    FocusLost,
     /// Raw data read
     ///
     /// A value of this variant can only be returned when using the
@@ -265,6 +569,8 @@ pub enum Key {
     PageUp,
     /// PageDown
     PageDown,
+    /// Begin; the center key of the keypad, with Num Lock off
+    Begin,
     /// Character key
     Char(char),
     /// Control character
@@ -285,6 +591,61 @@ pub enum Key {
     F(u32),
 }
 
+/// Represents a keyboard key event with modifier keys held
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct KeyEvent {
+    /// Key pressed or released
+    pub key: Key,
+    /// Modifier keys held when the key was pressed
+    ///
+    /// # Notes
+    ///
+    /// For `Char(_)` keys, the Shift key is reflected in the character value
+    /// and is not reported as a modifier.
+    ///
+    /// On Unix, modifier keys are reported only for certain special keys
+    /// and for characters sent with an Alt (`ESC`) prefix. Not all terminals
+    /// report all combinations of modifier keys.
+    pub modifiers: ModifierState,
+    /// Type of key event
+    ///
+    /// Key repeat and release events are only reported if
+    /// `enable_kitty_keyboard` is set in [`PrepareConfig`].
+    ///
+    /// [`PrepareConfig`]: struct.PrepareConfig.html
+    pub kind: KeyEventKind,
+}
+
+impl KeyEvent {
+    pub(crate) fn new(key: Key, modifiers: ModifierState) -> KeyEvent {
+        KeyEvent{
+            key,
+            modifiers,
+            kind: KeyEventKind::Press,
+        }
+    }
+
+    pub(crate) fn into_event(self) -> Event {
+        if self.modifiers.is_empty() && self.kind == KeyEventKind::Press {
+            Event::Key(self.key)
+        } else {
+            Event::KeyMod(self)
+        }
+    }
+}
+
+/// Represents the type of a keyboard key event
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq, Hash)]
+pub enum KeyEventKind {
+    /// A key was pressed
+    #[default]
+    Press,
+    /// A key was held, generating repeated input
+    Repeat,
+    /// A key was released
+    Release,
+}
+
 impl From<char> for Key {
     fn from(ch: char) -> Key {
         use crate::util::{is_ctrl, unctrl_lower};
@@ -301,7 +662,11 @@ impl From<char> for Key {
 }
 
 /// Represents a mouse event
+///
+/// Fields may be added in future versions; a `MouseEvent` is created
+/// using `MouseEvent::new`.
 #[derive(Copy, Clone, Debug, Eq, PartialEq)]
+#[non_exhaustive]
 pub struct MouseEvent {
     /// The position of the mouse within the terminal when the event occurred
     pub position: Cursor,
@@ -314,13 +679,58 @@ pub struct MouseEvent {
     /// On some systems, certain combinations of mouse button and modifier may
     /// be interpreted by the system and not reported as terminal events.
     pub modifiers: ModifierState,
+    /// For `ButtonPressed` events, the number of consecutive clicks of the
+    /// button, including this one; e.g. `2` indicates a double click.
+    ///
+    /// Clicks are counted only if `PrepareConfig::click_interval` is set;
+    /// otherwise, this value is always `1` for `ButtonPressed` events.
+    ///
+    /// For all other events, this value is `0`.
+    pub click_count: u32,
+    /// The position of the mouse in pixels, if reported by the terminal
+    ///
+    /// This value is `Some(_)` only if `PrepareConfig::enable_mouse_pixels`
+    /// is `true` and pixel positions are supported by the terminal.
+    pub pixel_position: Option<PixelPosition>,
+}
+
+impl MouseEvent {
+    /// Creates a new mouse event with the given values.
+    ///
+    /// `click_count` is `1` for `ButtonPressed` events and `0` otherwise;
+    /// `pixel_position` is `None`.
+    pub fn new(position: Cursor, input: MouseInput, modifiers: ModifierState) -> MouseEvent {
+        let click_count = match input {
+            MouseInput::ButtonPressed(_) => 1,
+            _ => 0
+        };
+
+        MouseEvent{
+            position,
+            input,
+            modifiers,
+            click_count,
+            pixel_position: None,
+        }
+    }
+}
+
+/// Represents a position within the terminal, in pixels
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub struct PixelPosition {
+    /// Horizontal offset from the left edge of the terminal, beginning at `0`.
+    pub x: usize,
+    /// Vertical offset from the top edge of the terminal, beginning at `0`.
+    pub y: usize,
 }
 
 /// Represents the type of mouse input event
 #[derive(Copy, Clone, Debug, Eq, PartialEq)]
 pub enum MouseInput {
-    /// The mouse cursor was moved
+    /// The mouse cursor was moved while no button was pressed
     Motion,
+    /// The mouse cursor was moved while a button was pressed
+    Drag(MouseButton),
     /// A mouse button was pressed
     ButtonPressed(MouseButton),
     /// A mouse button was released
@@ -329,6 +739,10 @@ pub enum MouseInput {
     WheelUp,
     /// The mouse wheel was scrolled down
     WheelDown,
+    /// The mouse wheel was scrolled left
+    WheelLeft,
+    /// The mouse wheel was scrolled right
+    WheelRight,
 }
 
 /// Represents a button on a mouse device
@@ -436,6 +850,79 @@ pub struct PrepareConfig {
     ///
     /// The default is `false`.
     pub always_track_motion: bool,
+    /// If `Some(_)`, successive presses of the same mouse button at the same
+    /// position, each within the given duration of the last, are counted and
+    /// reported in `MouseEvent::click_count`.
+    ///
+    /// Mouse events are only reported if `enable_mouse` is `true`.
+    ///
+    /// The default is `None`.
+    pub click_interval: Option<Duration>,
+    /// If `true`, mouse events will report the position of the mouse
+    /// in pixels, in addition to the cell position.
+    ///
+    /// Mouse events are only reported if `enable_mouse` is `true`.
+    ///
+    /// The default is `false`.
+    ///
+    /// # Notes
+    ///
+    /// This setting requires that the terminal support SGR-Pixels mouse
+    /// reporting and report its size in pixels. It should be enabled only
+    /// for terminals known to support it, as mouse positions reported by
+    /// other terminals may be misinterpreted.
+    ///
+    /// On Windows, this setting has no effect.
+    pub enable_mouse_pixels: bool,
+    /// If `true`, the terminal will be configured to report pasted text
+    /// as a single `Event::Paste(_)` rather than a series of key events.
+    ///
+    /// The default is `false`.
+    ///
+    /// # Notes
+    ///
+    /// This setting may not be supported on all systems.
+    ///
+    /// On Windows, this setting has no effect.
+    pub enable_bracketed_paste: bool,
+    /// If `true`, the terminal will be configured to report changes in
+    /// input focus and `read_event` may return `Event::FocusGained` or
+    /// `Event::FocusLost`.
+    ///
+    /// The default is `false`.
+    ///
+    /// # Notes
+    ///
+    /// This setting may not be supported on all systems.
+    pub enable_focus_events: bool,
+    /// If `true`, the terminal will be configured to use the kitty keyboard
+    /// protocol, if supported. Key input is then reported unambiguously;
+    /// e.g. Ctrl-I is distinguished from Tab and Escape from an Alt prefix.
+    /// Key repeat and release events are also reported.
+    ///
+    /// The default is `false`.
+    ///
+    /// # Notes
+    ///
+    /// This setting may not be supported on all systems.
+    ///
+    /// On Windows, this setting has no effect.
+    pub enable_kitty_keyboard: bool,
+    /// If `Some(_)`, when input received may be the beginning of an
+    /// incomplete escape sequence, `read_event` will wait up to the given
+    /// duration for the remainder of the sequence to arrive.
+    ///
+    /// This prevents sequences which arrive in separate reads, e.g. over a
+    /// slow network connection, from being reported as `Key::Escape` followed
+    /// by a series of characters. However, the Escape key is then reported
+    /// only after the timeout has elapsed.
+    ///
+    /// The default is `None`.
+    ///
+    /// # Notes
+    ///
+    /// On Windows, this setting has no effect.
+    pub escape_timeout: Option<Duration>,
     /// For each signal in the set, a signal handler will intercept the signal
     /// and report it by returning an `Event::Signal(_)` value.
     ///
@@ -453,11 +940,104 @@ impl Default for PrepareConfig {
             enable_keypad: true,
             enable_mouse: false,
             always_track_motion: false,
+            click_interval: None,
+            enable_mouse_pixels: false,
+            enable_bracketed_paste: false,
+            enable_focus_events: false,
+            enable_kitty_keyboard: false,
+            escape_timeout: None,
             report_signals: SignalSet::new(),
         }
     }
 }
 
+/// Represents a request for information sent to the terminal
+///
+/// A query is sent using [`TerminalReadGuard::query`]. The terminal's
+/// response is returned as a [`Reply`] value of the corresponding variant.
+///
+/// [`TerminalReadGuard::query`]: struct.TerminalReadGuard.html#method.query
+/// [`Reply`]: enum.Reply.html
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Query {
+    /// Requests primary device attributes (DA1)
+    PrimaryDeviceAttributes,
+    /// Requests secondary device attributes (DA2)
+    SecondaryDeviceAttributes,
+    /// Requests the name and version of the terminal (XTVERSION)
+    Version,
+    /// Requests the value of a terminfo capability
+    /// known to the terminal (XTGETTCAP)
+    Capability(String),
+    /// Requests the status of a DEC private mode (DECRQM)
+    Mode(u32),
+    /// Requests the default foreground color (OSC 10)
+    ForegroundColor,
+    /// Requests the default background color (OSC 11)
+    BackgroundColor,
+    /// Requests the contents of a selection buffer (OSC 52)
+    ///
+    /// Many terminals ignore this request unless reading the clipboard
+    /// has been explicitly permitted by the user.
+    Clipboard(Selection),
+}
+
+/// Represents the terminal's response to a [`Query`]
+///
+/// [`Query`]: enum.Query.html
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Reply {
+    /// Primary device attributes; the first value indicates
+    /// the terminal's conformance level and the remainder its features.
+    PrimaryDeviceAttributes(Vec<u32>),
+    /// Secondary device attributes; terminal type, firmware version,
+    /// and, for some terminals, additional values.
+    SecondaryDeviceAttributes(Vec<u32>),
+    /// Name and version of the terminal, e.g. `"XTerm(367)"`
+    Version(String),
+    /// Value of the named capability, or `None` if the capability
+    /// is not known to the terminal
+    Capability(String, Option<String>),
+    /// Status of the given mode
+    Mode(u32, ModeStatus),
+    /// Default foreground color, as `Color::Rgb(_, _, _)`
+    ForegroundColor(Color),
+    /// Default background color, as `Color::Rgb(_, _, _)`
+    BackgroundColor(Color),
+    /// Contents of the given selection buffer
+    Clipboard(Selection, String),
+}
+
+/// Represents the status of a terminal mode, as reported
+/// in response to `Query::Mode(_)`
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum ModeStatus {
+    /// The mode is not recognized by the terminal
+    NotRecognized,
+    /// The mode is set
+    Set,
+    /// The mode is reset
+    Reset,
+    /// The mode is set and cannot be changed
+    PermanentlySet,
+    /// The mode is reset and cannot be changed
+    PermanentlyReset,
+}
+
+/// Represents a selection buffer which may be set using
+/// [`Terminal::set_clipboard`]
+///
+/// [`Terminal::set_clipboard`]: struct.Terminal.html#method.set_clipboard
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Selection {
+    /// The system clipboard
+    Clipboard,
+    /// The primary selection
+    Primary,
+    /// The secondary selection
+    Secondary,
+}
+
 /// Represents a previous device state of a [`Terminal`].
 ///
 /// A value of this type is returned by [`Terminal::prepare`].
@@ -503,6 +1083,15 @@ impl Size {
     }
 }
 
+/// Represents a size in pixels
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct PixelSize {
+    /// Width, in pixels
+    pub width: usize,
+    /// Height, in pixels
+    pub height: usize,
+}
+
 /// Provides concurrent read and write access to a terminal device
 ///
 /// # Concurrency
@@ -555,6 +1144,24 @@ impl Terminal {
         self.0.name()
     }
 
+    /// Returns the color depth supported by the terminal.
+    ///
+    /// Colors which cannot be displayed by the terminal are replaced
+    /// with the nearest supported color.
+    ///
+    /// # Notes
+    ///
+    /// On Unix, support for direct (24-bit) color is detected using the
+    /// `COLORTERM` environment variable and the `RGB` or `Tc` terminfo
+    /// capabilities. Otherwise, the color depth is determined by the
+    /// `colors` terminfo capability.
+    ///
+    /// On Windows, this method always returns `ColorDepth::Color16`.
+    #[inline]
+    pub fn color_depth(&self) -> ColorDepth {
+        self.0.color_depth()
+    }
+
     /// Attempts to acquire an exclusive lock on terminal read operations.
     ///
     /// The current thread will block until the lock can be acquired.
@@ -621,6 +1228,78 @@ impl Terminal {
     pub fn restore(&self, state: PrepareState) -> io::Result<()> {
         self.0.restore(state.0)
     }
+
+    /// Returns the size of the terminal window in pixels.
+    ///
+    /// If the size is not reported by the operating system,
+    /// the terminal is queried, waiting briefly for a response.
+    ///
+    /// Returns `Ok(None)` if the size cannot be determined.
+    pub fn pixel_size(&self) -> io::Result<Option<PixelSize>> {
+        self.0.pixel_size()
+    }
+
+    /// Returns the size of a single character cell in pixels.
+    ///
+    /// If the size is not reported by the operating system,
+    /// the terminal is queried, waiting briefly for a response.
+    ///
+    /// Returns `Ok(None)` if the size cannot be determined.
+    pub fn cell_size(&self) -> io::Result<Option<PixelSize>> {
+        self.0.cell_size()
+    }
+
+    /// Returns the current position of the cursor.
+    ///
+    /// Any output buffered is flushed before the cursor position is queried.
+    /// Input which arrives before the terminal responds is retained and will
+    /// be returned by subsequent calls to `read_event`.
+    ///
+    /// Returns `Ok(None)` if `timeout` elapses without a response.
+    /// If `timeout` is `None`, this method will wait indefinitely.
+    pub fn cursor_position(&self, timeout: Option<Duration>) -> io::Result<Option<Cursor>> {
+        self.0.cursor_position(timeout)
+    }
+
+    /// Sends a query to the terminal and waits for its reply.
+    ///
+    /// Any output buffered is flushed before the query is sent.
+    /// Input which arrives before the terminal responds is retained and will
+    /// be returned by subsequent calls to `read_event`.
+    ///
+    /// Returns `Ok(None)` if `timeout` elapses without a reply.
+    /// If `timeout` is `None`, this method will wait indefinitely.
+    ///
+    /// # Notes
+    ///
+    /// Terminals which do not support a query will not reply to it.
+    ///
+    /// On Windows, this method always returns `Ok(None)`.
+    pub fn query(&self, query: &Query, timeout: Option<Duration>) -> io::Result<Option<Reply>> {
+        self.0.query(query, timeout)
+    }
+
+    /// Returns the background color of the terminal,
+    /// as `Color::Rgb(_, _, _)`.
+    ///
+    /// Returns `Ok(None)` if the background color cannot be determined.
+    ///
+    /// [`Color::is_light`] may be used to determine whether the background
+    /// is light or dark.
+    ///
+    /// # Notes
+    ///
+    /// On Unix, the terminal is queried, waiting briefly for a response.
+    /// If the terminal does not respond, the `COLORFGBG` environment
+    /// variable is consulted.
+    ///
+    /// On Windows, the background color is approximated from
+    /// the console's default text attributes.
+    ///
+    /// [`Color::is_light`]: enum.Color.html#method.is_light
+    pub fn background_color(&self) -> io::Result<Option<Color>> {
+        self.0.background_color()
+    }
 }
 
 /// # Locking
@@ -726,6 +1405,31 @@ impl Terminal {
         self.0.set_cursor_mode(mode)
     }
 
+    /// Sets the title of the terminal window.
+    ///
+    /// The original title and icon name are saved on the first call and are
+    /// restored when the terminal state is restored with `restore`
+    /// or when the `Terminal` is dropped.
+    ///
+    /// # Notes
+    ///
+    /// On some systems, this setting may have no effect.
+    pub fn set_title(&self, title: &str) -> io::Result<()> {
+        self.0.set_title(title)
+    }
+
+    /// Sets the contents of a selection buffer, such as the system clipboard.
+    ///
+    /// The text is sent to the terminal, rather than stored locally,
+    /// which allows it to reach the user's clipboard through a remote session.
+    ///
+    /// # Notes
+    ///
+    /// On some systems, this setting may have no effect.
+    pub fn set_clipboard(&self, selection: Selection, text: &str) -> io::Result<()> {
+        self.0.set_clipboard(selection, text)
+    }
+
     /// Adds a set of `Style` flags to the current style setting.
     pub fn add_style(&self, style: Style) -> io::Result<()> {
         self.0.add_style(style)
@@ -757,6 +1461,30 @@ impl Terminal {
         self.0.set_bg(bg.into())
     }
 
+    /// Sets the style of underlined text.
+    pub fn set_underline_style(&self, style: UnderlineStyle) -> io::Result<()> {
+        self.0.set_underline_style(style)
+    }
+
+    /// Sets or removes the color of underlined text.
+    ///
+    /// If `None`, underlines are displayed in the foreground color.
+    pub fn set_underline_color<C: Into<Option<Color>>>(&self, color: C) -> io::Result<()> {
+        self.0.set_underline_color(color.into())
+    }
+
+    /// Starts or ends a hyperlink.
+    ///
+    /// Text written while a link is active is displayed as a hyperlink
+    /// to the given URI. Passing `None` ends the link.
+    ///
+    /// # Notes
+    ///
+    /// On some systems, this setting may have no effect.
+    pub fn set_link<'b, L: Into<Option<&'b str>>>(&self, uri: L) -> io::Result<()> {
+        self.0.set_link(uri.into())
+    }
+
     /// Removes color and style attributes.
     pub fn clear_attributes(&self) -> io::Result<()> {
         self.0.clear_attributes()
@@ -906,6 +1634,164 @@ impl<'a> TerminalReadGuard<'a> {
         self.0.restore_with_lock(&mut writer.0, state.0)
     }
 
+    /// Returns the size of the terminal window in pixels.
+    ///
+    /// If the size is not reported by the operating system,
+    /// the terminal is queried, waiting briefly for a response.
+    ///
+    /// Returns `Ok(None)` if the size cannot be determined.
+    ///
+    /// ## Locking
+    ///
+    /// This method internally acquires the [`Terminal`] write lock.
+    ///
+    /// If the write lock is already held by the current thread,
+    /// call [`pixel_size_with_lock`], in order to prevent deadlocks.
+    ///
+    /// [`Terminal`]: struct.Terminal.html
+    /// [`pixel_size_with_lock`]: #method.pixel_size_with_lock
+    pub fn pixel_size(&mut self) -> io::Result<Option<PixelSize>> {
+        self.0.pixel_size()
+    }
+
+    /// Returns the size of the terminal window in pixels,
+    /// using both [`Terminal`] locks.
+    ///
+    /// [`Terminal`]: struct.Terminal.html
+    pub fn pixel_size_with_lock(&mut self, writer: &mut TerminalWriteGuard)
+            -> io::Result<Option<PixelSize>> {
+        self.0.pixel_size_with_lock(&mut writer.0)
+    }
+
+    /// Returns the size of a single character cell in pixels.
+    ///
+    /// If the size is not reported by the operating system,
+    /// the terminal is queried, waiting briefly for a response.
+    ///
+    /// Returns `Ok(None)` if the size cannot be determined.
+    ///
+    /// ## Locking
+    ///
+    /// This method internally acquires the [`Terminal`] write lock.
+    ///
+    /// If the write lock is already held by the current thread,
+    /// call [`cell_size_with_lock`], in order to prevent deadlocks.
+    ///
+    /// [`Terminal`]: struct.Terminal.html
+    /// [`cell_size_with_lock`]: #method.cell_size_with_lock
+    pub fn cell_size(&mut self) -> io::Result<Option<PixelSize>> {
+        self.0.cell_size()
+    }
+
+    /// Returns the size of a single character cell in pixels,
+    /// using both [`Terminal`] locks.
+    ///
+    /// [`Terminal`]: struct.Terminal.html
+    pub fn cell_size_with_lock(&mut self, writer: &mut TerminalWriteGuard)
+            -> io::Result<Option<PixelSize>> {
+        self.0.cell_size_with_lock(&mut writer.0)
+    }
+
+    /// Returns the current position of the cursor.
+    ///
+    /// Any output buffered is flushed before the cursor position is queried.
+    /// Input which arrives before the terminal responds is retained and will
+    /// be returned by subsequent calls to `read_event`.
+    ///
+    /// Returns `Ok(None)` if `timeout` elapses without a response.
+    /// If `timeout` is `None`, this method will wait indefinitely.
+    ///
+    /// ## Locking
+    ///
+    /// This method internally acquires the [`Terminal`] write lock.
+    ///
+    /// If the write lock is already held by the current thread,
+    /// call [`cursor_position_with_lock`], in order to prevent deadlocks.
+    ///
+    /// [`Terminal`]: struct.Terminal.html
+    /// [`cursor_position_with_lock`]: #method.cursor_position_with_lock
+    pub fn cursor_position(&mut self, timeout: Option<Duration>)
+            -> io::Result<Option<Cursor>> {
+        self.0.cursor_position(timeout)
+    }
+
+    /// Returns the current position of the cursor,
+    /// using both [`Terminal`] locks.
+    ///
+    /// [`Terminal`]: struct.Terminal.html
+    pub fn cursor_position_with_lock(&mut self, writer: &mut TerminalWriteGuard,
+            timeout: Option<Duration>) -> io::Result<Option<Cursor>> {
+        self.0.cursor_position_with_lock(&mut writer.0, timeout)
+    }
+
+    /// Sends a query to the terminal and waits for its reply.
+    ///
+    /// Any output buffered is flushed before the query is sent.
+    /// Input which arrives before the terminal responds is retained and will
+    /// be returned by subsequent calls to `read_event`.
+    ///
+    /// Returns `Ok(None)` if `timeout` elapses without a reply.
+    /// If `timeout` is `None`, this method will wait indefinitely.
+    ///
+    /// # Notes
+    ///
+    /// Terminals which do not support a query will not reply to it.
+    ///
+    /// On Windows, this method always returns `Ok(None)`.
+    ///
+    /// ## Locking
+    ///
+    /// This method internally acquires the [`Terminal`] write lock.
+    ///
+    /// If the write lock is already held by the current thread,
+    /// call [`query_with_lock`], in order to prevent deadlocks.
+    ///
+    /// [`Terminal`]: struct.Terminal.html
+    /// [`query_with_lock`]: #method.query_with_lock
+    pub fn query(&mut self, query: &Query, timeout: Option<Duration>)
+            -> io::Result<Option<Reply>> {
+        self.0.query(query, timeout)
+    }
+
+    /// Sends a query to the terminal and waits for its reply,
+    /// using both [`Terminal`] locks.
+    ///
+    /// [`Terminal`]: struct.Terminal.html
+    pub fn query_with_lock(&mut self, writer: &mut TerminalWriteGuard,
+            query: &Query, timeout: Option<Duration>) -> io::Result<Option<Reply>> {
+        self.0.query_with_lock(&mut writer.0, query, timeout)
+    }
+
+    /// Returns the background color of the terminal,
+    /// as `Color::Rgb(_, _, _)`.
+    ///
+    /// Returns `Ok(None)` if the background color cannot be determined.
+    ///
+    /// See [`Terminal::background_color`] for details.
+    ///
+    /// ## Locking
+    ///
+    /// This method internally acquires the [`Terminal`] write lock.
+    ///
+    /// If the write lock is already held by the current thread,
+    /// call [`background_color_with_lock`], in order to prevent deadlocks.
+    ///
+    /// [`Terminal`]: struct.Terminal.html
+    /// [`Terminal::background_color`]: struct.Terminal.html#method.background_color
+    /// [`background_color_with_lock`]: #method.background_color_with_lock
+    pub fn background_color(&mut self) -> io::Result<Option<Color>> {
+        self.0.background_color()
+    }
+
+    /// Returns the background color of the terminal,
+    /// using both [`Terminal`] locks.
+    ///
+    /// [`Terminal`]: struct.Terminal.html
+    pub fn background_color_with_lock(&mut self, writer: &mut TerminalWriteGuard)
+            -> io::Result<Option<Color>> {
+        self.0.background_color_with_lock(&mut writer.0)
+    }
+
     /// Waits for an event from the terminal.
     ///
     /// Returns `Ok(false)` if `timeout` elapses without an event occurring.
@@ -995,6 +1881,31 @@ impl<'a> TerminalWriteGuard<'a> {
         self.0.set_cursor_mode(mode)
     }
 
+    /// Sets the title of the terminal window.
+    ///
+    /// The original title and icon name are saved on the first call and are
+    /// restored when the terminal state is restored with `restore`
+    /// or when the `Terminal` is dropped.
+    ///
+    /// # Notes
+    ///
+    /// On some systems, this setting may have no effect.
+    pub fn set_title(&mut self, title: &str) -> io::Result<()> {
+        self.0.set_title(title)
+    }
+
+    /// Sets the contents of a selection buffer, such as the system clipboard.
+    ///
+    /// The text is sent to the terminal, rather than stored locally,
+    /// which allows it to reach the user's clipboard through a remote session.
+    ///
+    /// # Notes
+    ///
+    /// On some systems, this setting may have no effect.
+    pub fn set_clipboard(&mut self, selection: Selection, text: &str) -> io::Result<()> {
+        self.0.set_clipboard(selection, text)
+    }
+
     /// Adds a set of `Style` flags to the current style setting.
     pub fn add_style(&mut self, style: Style) -> io::Result<()> {
         self.0.add_style(style)
@@ -1026,6 +1937,30 @@ impl<'a> TerminalWriteGuard<'a> {
         self.0.set_bg(bg.into())
     }
 
+    /// Sets the style of underlined text.
+    pub fn set_underline_style(&mut self, style: UnderlineStyle) -> io::Result<()> {
+        self.0.set_underline_style(style)
+    }
+
+    /// Sets or removes the color of underlined text.
+    ///
+    /// If `None`, underlines are displayed in the foreground color.
+    pub fn set_underline_color<C: Into<Option<Color>>>(&mut self, color: C) -> io::Result<()> {
+        self.0.set_underline_color(color.into())
+    }
+
+    /// Starts or ends a hyperlink.
+    ///
+    /// Text written while a link is active is displayed as a hyperlink
+    /// to the given URI. Passing `None` ends the link.
+    ///
+    /// # Notes
+    ///
+    /// On some systems, this setting may have no effect.
+    pub fn set_link<'b, L: Into<Option<&'b str>>>(&mut self, uri: L) -> io::Result<()> {
+        self.0.set_link(uri.into())
+    }
+
     /// Adds bold to the current style setting.
     pub fn clear_attributes(&mut self) -> io::Result<()> {
         self.0.clear_attributes()
@@ -1149,3 +2084,39 @@ impl<'a> crate::windows::TerminalExt for TerminalReadGuard<'a> {
         self.0.read_raw_event(events, timeout)
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::{Color, ColorDepth};
+
+    #[test]
+    fn test_quantize_named() {
+        assert_eq!(Color::Red.quantize(ColorDepth::Color8), Color::Red);
+        assert_eq!(Color::BrightRed.quantize(ColorDepth::Color256), Color::BrightRed);
+        assert_eq!(Color::BrightRed.quantize(ColorDepth::Color16), Color::BrightRed);
+        assert_eq!(Color::BrightRed.quantize(ColorDepth::Color8), Color::Red);
+        assert_eq!(Color::BrightBlack.quantize(ColorDepth::Color8), Color::Black);
+    }
+
+    #[test]
+    fn test_quantize_indexed() {
+        assert_eq!(Color::Indexed(1).quantize(ColorDepth::Color16), Color::Red);
+        assert_eq!(Color::Indexed(12).quantize(ColorDepth::Color16), Color::BrightBlue);
+        assert_eq!(Color::Indexed(12).quantize(ColorDepth::Color8), Color::Blue);
+        assert_eq!(Color::Indexed(196).quantize(ColorDepth::Color256), Color::Indexed(196));
+        assert_eq!(Color::Indexed(196).quantize(ColorDepth::Color16), Color::BrightRed);
+        assert_eq!(Color::Indexed(232).quantize(ColorDepth::Color16), Color::Black);
+        assert_eq!(Color::Indexed(255).quantize(ColorDepth::Color8), Color::White);
+    }
+
+    #[test]
+    fn test_quantize_rgb() {
+        assert_eq!(Color::Rgb(255, 0, 0).quantize(ColorDepth::Color256), Color::Indexed(196));
+        assert_eq!(Color::Rgb(0, 0, 0).quantize(ColorDepth::Color256), Color::Indexed(16));
+        assert_eq!(Color::Rgb(128, 128, 128).quantize(ColorDepth::Color256), Color::Indexed(244));
+        assert_eq!(Color::Rgb(0, 0, 0).quantize(ColorDepth::Color16), Color::Black);
+        assert_eq!(Color::Rgb(255, 255, 255).quantize(ColorDepth::Color16), Color::BrightWhite);
+        assert_eq!(Color::Rgb(255, 255, 255).quantize(ColorDepth::Color8), Color::White);
+        assert_eq!(Color::Rgb(0, 200, 200).quantize(ColorDepth::Color8), Color::Cyan);
+    }
+}
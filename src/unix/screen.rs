@@ -2,13 +2,16 @@ use std::io;
 use std::sync::{LockResult, Mutex, MutexGuard, TryLockResult};
 use std::time::Duration;
 
-use crate::buffer::ScreenBuffer;
+use crate::buffer::{Cell, ScreenBuffer};
 use crate::priv_util::{
     map_lock_result, map_try_lock_result,
     map2_lock_result, map2_try_lock_result,
 };
 use crate::sys::{Terminal, TerminalReadGuard, TerminalWriteGuard, PrepareState};
-use crate::terminal::{Color, Cursor, CursorMode, Event, Size, Style, PrepareConfig};
+use crate::terminal::{
+    Color, ColorDepth, Cursor, CursorMode, Event, PixelSize, Size, PrepareConfig,
+    Selection, Theme,
+};
 
 pub struct Screen {
     term: Terminal,
@@ -27,16 +30,45 @@ pub struct ScreenWriteGuard<'a> {
     data: MutexGuard<'a, Writer>,
 }
 
+// Methods of moving the cursor during refresh
+#[derive(Copy, Clone, Debug)]
+enum CursorMove {
+    // Moves to an absolute position using `cup`
+    Absolute,
+    // Moves relative to the current position
+    Relative,
+    // Moves to the first column, then relative to that position
+    Return,
+    // Moves using `vpa` and `hpa`
+    Address,
+    // Moves forward by writing the unchanged cells in between
+    Rewrite,
+}
+
 struct Writer {
     buffer: ScreenBuffer,
     clear_screen: bool,
     real_cursor: Cursor,
+    // Whether refresh is wrapped in a synchronized update
+    sync_output: bool,
 }
 
 impl Screen {
     pub fn new(term: Terminal, config: PrepareConfig) -> io::Result<Screen> {
         let size = term.size()?;
-        let state = term.prepare(config)?;
+
+        // Some terminals, such as kitty, keep separate keyboard mode stacks
+        // for the main and alternate screens. Modes must be pushed after
+        // entering the alternate screen and popped before leaving it.
+        term.enter_screen()?;
+
+        let state = match term.prepare(config) {
+            Ok(state) => state,
+            Err(e) => {
+                let _ = term.exit_screen();
+                return Err(e);
+            }
+        };
 
         let screen = Screen{
             term: term,
@@ -45,11 +77,16 @@ impl Screen {
             writer: Mutex::new(Writer{
                 buffer: ScreenBuffer::new(size),
                 clear_screen: false,
-                real_cursor: Cursor::default(),
+                // Force cursor move on first refresh
+                real_cursor: (!0, !0).into(),
+                sync_output: false,
             }),
         };
 
-        screen.term.enter_screen()?;
+        // The terminal may be queried, which requires it to be prepared.
+        // On error, the dropped `Screen` restores the terminal.
+        let sync_output = screen.term.has_sync_output()?;
+        screen.lock_write_data().sync_output = sync_output;
 
         Ok(screen)
     }
@@ -100,10 +137,34 @@ impl Screen {
         self.term.name()
     }
 
+    pub fn color_depth(&self) -> ColorDepth {
+        self.term.color_depth()
+    }
+
+    pub fn pixel_size(&self) -> io::Result<Option<PixelSize>> {
+        self.term.pixel_size()
+    }
+
+    pub fn cell_size(&self) -> io::Result<Option<PixelSize>> {
+        self.term.cell_size()
+    }
+
+    pub fn background_color(&self) -> io::Result<Option<Color>> {
+        self.term.background_color()
+    }
+
     pub fn set_cursor_mode(&self, mode: CursorMode) -> io::Result<()> {
         self.term.set_cursor_mode(mode)
     }
 
+    pub fn set_title(&self, title: &str) -> io::Result<()> {
+        self.term.set_title(title)
+    }
+
+    pub fn set_clipboard(&self, selection: Selection, text: &str) -> io::Result<()> {
+        self.term.set_clipboard(selection, text)
+    }
+
     pub fn wait_event(&self, timeout: Option<Duration>) -> io::Result<bool> {
         self.lock_reader().wait_event(timeout)
     }
@@ -177,54 +238,300 @@ impl<'a> ScreenWriteGuard<'a> {
         self.writer.set_cursor_mode(mode)
     }
 
+    pub fn set_title(&mut self, title: &str) -> io::Result<()> {
+        self.writer.set_title(title)
+    }
+
+    pub fn set_clipboard(&mut self, selection: Selection, text: &str) -> io::Result<()> {
+        self.writer.set_clipboard(selection, text)
+    }
+
     pub fn refresh(&mut self) -> io::Result<()> {
+        // The terminal presents the frame once it is completely drawn
+        if self.data.sync_output {
+            self.writer.begin_sync_output()?;
+        }
+
+        let res = self.draw();
+
+        // Synchronized output is ended even if drawing fails;
+        // otherwise, the terminal would cease to present updates.
+        if self.data.sync_output {
+            let end = self.writer.end_sync_output();
+            let flush = self.writer.flush();
+            return res.and(end).and(flush);
+        }
+
+        res.and_then(|_| self.writer.flush())
+    }
+
+    fn draw(&mut self) -> io::Result<()> {
+        self.writer.clear_attributes()?;
+
         if self.data.clear_screen {
             self.writer.clear_screen()?;
             self.data.clear_screen = false;
+            // Force cursor move after clearing the screen
+            self.data.real_cursor = (!0, !0).into();
+        } else {
+            self.scroll_lines()?;
         }
 
-        self.writer.clear_attributes()?;
-
         let mut indices = self.data.buffer.indices();
+        // Most recently written cell, whose attributes are currently set
+        let mut last = None;
 
         while let Some((pos, cell)) = self.data.buffer.next_cell(&mut indices) {
-            self.move_cursor(pos)?;
-
-            self.apply_attrs(cell.attrs())?;
-            self.writer.write_str(cell.text())?;
-            self.data.real_cursor.column += 1;
+            let run = self.data.buffer.run_length(pos);
+
+            if self.erase_cells(pos, &cell, run, last.as_ref())? {
+                self.data.buffer.skip_cells(&mut indices, run - 1);
+            } else {
+                self.move_cursor(pos, last.as_ref())?;
+
+                self.apply_attrs(cell.attrs())?;
+                self.writer.set_link(self.data.buffer.link(&cell))?;
+
+                if run > 1 && self.repeat_cell(&cell, run)? {
+                    self.data.buffer.skip_cells(&mut indices, run - 1);
+                    self.data.real_cursor.column += run;
+                } else {
+                    self.writer.write_str(&self.data.buffer.text(&cell))?;
+                    self.data.real_cursor.column += cell.width();
+                }
+            }
+
+            last = Some(cell);
         }
 
+        self.writer.set_link(None)?;
         self.writer.clear_attributes()?;
 
         let size = self.data.buffer.size();
         let pos = self.data.buffer.cursor();
 
         if pos.is_out_of_bounds(size) {
-            self.move_cursor(Cursor::last(size))?;
+            self.move_cursor(Cursor::last(size), None)?;
+        } else {
+            self.move_cursor(pos, None)?;
+        }
+
+        Ok(())
+    }
+
+    // Moves lines which have shifted vertically since the last refresh,
+    // so that only lines newly exposed by the shift need to be redrawn.
+    fn scroll_lines(&mut self) -> io::Result<()> {
+        if let Some(scroll) = self.data.buffer.find_scroll() {
+            let lines = self.data.buffer.size().lines;
+
+            if self.writer.scroll_lines(&scroll, lines)? {
+                self.data.buffer.apply_scroll(&scroll);
+                // Cursor position is unknown after changing scroll region
+                self.data.real_cursor = (!0, !0).into();
+            }
+        }
+
+        Ok(())
+    }
+
+    // Erases a run of blank cells using `el` or `ech`, if doing so is
+    // cheaper than writing spaces. Returns whether the cells were erased.
+    fn erase_cells(&mut self, pos: Cursor, cell: &Cell, run: usize,
+            last: Option<&Cell>) -> io::Result<bool> {
+        let attrs = cell.attrs();
+
+        // Erased cells take on the background color only if `bce` is set
+        if cell.char() != Some(' ') || self.data.buffer.link(cell).is_some() ||
+                !attrs.style.is_empty() ||
+                (attrs.bg.is_some() && !self.writer.has_back_color_erase()) {
+            return Ok(false);
+        }
+
+        let to_end = pos.column + run == self.data.buffer.size().columns;
+
+        let cost = if to_end {
+            self.writer.measure(|w| w.clear_to_line_end())
+        } else {
+            // The cursor does not move, so skipping the erased cells
+            // is also likely to be required.
+            self.writer.measure(|w| {
+                w.erase_chars(run)?;
+                w.move_right(run)
+            })
+        };
+
+        match cost {
+            Some(cost) if cost < run => (),
+            _ => return Ok(false)
+        }
+
+        self.move_cursor(pos, last)?;
+
+        self.apply_attrs(attrs)?;
+        self.writer.set_link(None)?;
+
+        if to_end {
+            self.writer.clear_to_line_end()?;
         } else {
-            self.move_cursor(pos)?;
+            self.writer.erase_chars(run)?;
         }
 
-        self.writer.flush()
+        Ok(true)
+    }
+
+    // Writes a run of identical cells using `rep`, if doing so is cheaper
+    // than writing each cell. Returns whether the cells were written.
+    fn repeat_cell(&mut self, cell: &Cell, run: usize) -> io::Result<bool> {
+        let ch = match cell.char() {
+            Some(ch) if ch.is_ascii() => ch,
+            _ => return Ok(false)
+        };
+
+        match self.writer.measure(|w| w.repeat_char(ch, run)) {
+            Some(cost) if cost < run => {
+                self.writer.repeat_char(ch, run)?;
+                Ok(true)
+            }
+            _ => Ok(false)
+        }
     }
 
-    fn move_cursor(&mut self, pos: Cursor) -> io::Result<()> {
-        if self.data.real_cursor != pos {
-            self.writer.move_cursor(pos)?;
-            self.data.real_cursor = pos;
+    // Moves the cursor using whichever method writes the fewest bytes.
+    //
+    // `last` is the most recently written cell. Unchanged cells having
+    // the same attributes may be rewritten to move the cursor forward.
+    fn move_cursor(&mut self, pos: Cursor, last: Option<&Cell>) -> io::Result<()> {
+        let real = self.data.real_cursor;
+
+        if real == pos {
+            return Ok(());
+        }
+
+        let mut best = CursorMove::Absolute;
+        let mut best_cost = usize::MAX;
+
+        // Relative movement is possible only if the cursor position is known
+        let moves: &[CursorMove] = if real.is_out_of_bounds(self.data.buffer.size()) {
+            if real.line < self.data.buffer.size().lines {
+                // The cursor is past the end of the line
+                &[CursorMove::Absolute, CursorMove::Return, CursorMove::Address]
+            } else {
+                &[CursorMove::Absolute]
+            }
+        } else {
+            &[CursorMove::Absolute, CursorMove::Relative, CursorMove::Return,
+                CursorMove::Address, CursorMove::Rewrite]
+        };
+
+        for &mv in moves {
+            if let Some(cost) = self.move_cost(mv, pos, last, best_cost) {
+                if cost < best_cost {
+                    best = mv;
+                    best_cost = cost;
+                }
+            }
+        }
+
+        match best {
+            CursorMove::Absolute => self.writer.move_cursor(pos)?,
+            CursorMove::Relative => move_relative(&mut self.writer, real, pos)?,
+            CursorMove::Return => {
+                self.writer.move_to_first_column()?;
+                move_relative(&mut self.writer, Cursor{line: real.line, column: 0}, pos)?;
+            }
+            CursorMove::Address => move_address(&mut self.writer, real, pos)?,
+            CursorMove::Rewrite => {
+                let cells = self.data.buffer.cells(real, pos.column - real.column);
+
+                for cell in cells {
+                    self.writer.write_str(&self.data.buffer.text(cell))?;
+                }
+            }
         }
 
+        self.data.real_cursor = pos;
         Ok(())
     }
 
-    fn apply_attrs(&mut self,
-            (fg, bg, style): (Option<Color>, Option<Color>, Style))
-            -> io::Result<()> {
-        self.writer.set_attrs(fg, bg, style)
+    // Returns the number of bytes written to move the cursor to `pos`
+    // using the given method, or `None` if the method cannot be used.
+    fn move_cost(&mut self, mv: CursorMove, pos: Cursor, last: Option<&Cell>,
+            limit: usize) -> Option<usize> {
+        let real = self.data.real_cursor;
+
+        match mv {
+            CursorMove::Absolute => self.writer.measure(|w| w.move_cursor(pos)),
+            CursorMove::Relative => self.writer.measure(|w| move_relative(w, real, pos)),
+            CursorMove::Return => {
+                let start = Cursor{line: real.line, column: 0};
+                // Carriage return is a single byte
+                self.writer.measure(|w| move_relative(w, start, pos))
+                    .map(|n| n + 1)
+            }
+            CursorMove::Address => self.writer.measure(|w| move_address(w, real, pos)),
+            CursorMove::Rewrite => {
+                let last = last?;
+
+                if real.line != pos.line || real.column > pos.column {
+                    return None;
+                }
+
+                let mut cost = 0;
+
+                let buffer = &self.data.buffer;
+
+                for cell in buffer.cells(real, pos.column - real.column) {
+                    if cell.width() != 1 || cell.attrs() != last.attrs() ||
+                            buffer.link(cell) != buffer.link(last) {
+                        return None;
+                    }
+
+                    cost += buffer.text(cell).len();
+
+                    if cost >= limit {
+                        return None;
+                    }
+                }
+
+                Some(cost)
+            }
+        }
+    }
+
+    fn apply_attrs(&mut self, theme: Theme) -> io::Result<()> {
+        self.writer.set_theme(theme)
     }
 }
 
+fn move_relative(writer: &mut TerminalWriteGuard, from: Cursor, to: Cursor) -> io::Result<()> {
+    if to.line > from.line {
+        writer.move_down(to.line - from.line)?;
+    } else if to.line < from.line {
+        writer.move_up(from.line - to.line)?;
+    }
+
+    if to.column > from.column {
+        writer.move_right(to.column - from.column)?;
+    } else if to.column < from.column {
+        writer.move_left(from.column - to.column)?;
+    }
+
+    Ok(())
+}
+
+fn move_address(writer: &mut TerminalWriteGuard, from: Cursor, to: Cursor) -> io::Result<()> {
+    if to.line != from.line {
+        writer.move_to_line(to.line)?;
+    }
+
+    if to.column != from.column {
+        writer.move_to_column(to.column)?;
+    }
+
+    Ok(())
+}
+
 impl<'a> Drop for ScreenWriteGuard<'a> {
     fn drop(&mut self) {
         if let Err(e) = self.refresh() {
@@ -243,3 +550,38 @@ impl Writer {
         self.clear_screen = true;
     }
 }
+
+#[cfg(test)]
+mod test {
+    use libc::c_int;
+
+    use crate::sys::terminal::test::{open_pty_terminal, read_pty};
+    use crate::terminal::PrepareConfig;
+    use super::Screen;
+
+    fn find(out: &str, seq: &str) -> usize {
+        out.find(seq).unwrap_or_else(|| panic!("{:?} not found in {:?}", seq, out))
+    }
+
+    #[test]
+    fn test_screen_kitty_keyboard_order() {
+        let (master, term) = open_pty_terminal();
+
+        let screen = Screen::new(term, PrepareConfig{
+            enable_kitty_keyboard: true,
+            ..PrepareConfig::default()
+        }).unwrap();
+        drop(screen);
+
+        let out = read_pty(master);
+        unsafe { libc::close(master as c_int); }
+
+        let enter = find(&out, "\x1b[?1049h");
+        let push = find(&out, "\x1b[>7u");
+        let pop = find(&out, "\x1b[<u");
+        let exit = find(&out, "\x1b[?1049l");
+
+        assert!(enter < push && push < pop && pop < exit,
+            "unexpected sequence order: {:?}", out);
+    }
+}
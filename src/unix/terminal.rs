@@ -1,13 +1,16 @@
+use std::collections::VecDeque;
 use std::convert::TryFrom;
+use std::env;
 use std::fs::File;
 use std::io;
 use std::mem::{replace, zeroed};
+use std::ops::Range;
 use std::os::unix::io::{FromRawFd, IntoRawFd, RawFd};
 use std::path::Path;
 use std::str::from_utf8;
 use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::{LockResult, Mutex, MutexGuard, TryLockResult};
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 use libc::{
     ioctl,
@@ -31,41 +34,134 @@ use nix::unistd::{read, write};
 use smallstr::SmallString;
 
 use terminfo::{self, capability as cap, Database};
-use terminfo::capability::Expansion;
-use terminfo::expand::Context;
+use terminfo::capability::{Expansion, Value};
+use terminfo::expand::{Context, Expand, Parameter};
 
-use crate::priv_util::{map_lock_result, map_try_lock_result};
+use crate::buffer::Scroll;
+use crate::priv_util::{map_lock_result, map_try_lock_result, ClickCounter};
 use crate::sequence::{FindResult, SequenceMap};
 use crate::signal::{Signal, SignalSet};
 use crate::terminal::{
-    Color, Cursor, CursorMode, Event, Key, PrepareConfig, Size, Style, Theme,
-    MouseButton, MouseEvent, MouseInput, ModifierState,
+    Color, ColorDepth, Cursor, CursorMode, Event, Key, KeyEvent, KeyEventKind,
+    PrepareConfig,
+    Size, Style, Theme, UnderlineStyle,
+    MouseButton, MouseEvent, MouseInput, ModifierState, PixelPosition, PixelSize,
+    ModeStatus, Query, Reply, Selection,
 };
 use crate::util::prefixes;
 
 const OUT_BUFFER_SIZE: usize = 8192;
 
-const XTERM_ENABLE_MOUSE: &str = "\x1b[?1006h\x1b[?1002h";
-const XTERM_DISABLE_MOUSE: &str = "\x1b[?1006l\x1b[?1002l";
+// Enables button event tracking, preferring SGR (1006) encoding of
+// mouse events over urxvt (1015) encoding, where supported.
+const XTERM_ENABLE_MOUSE: &str = "\x1b[?1002h\x1b[?1015h\x1b[?1006h";
+const XTERM_DISABLE_MOUSE: &str = "\x1b[?1016l\x1b[?1006l\x1b[?1015l\x1b[?1002l";
+const XTERM_ENABLE_MOUSE_PIXELS: &str = "\x1b[?1016h";
 const XTERM_ENABLE_MOUSE_MOTION: &str = "\x1b[?1003h";
 const XTERM_DISABLE_MOUSE_MOTION: &str = "\x1b[?1003l";
 const XTERM_MOUSE_INTRO: &str = "\x1b[<";
+const X10_MOUSE_INTRO: &str = "\x1b[M";
+const XTERM_CSI: &str = "\x1b[";
+const XTERM_ENABLE_PASTE: &str = "\x1b[?2004h";
+const XTERM_DISABLE_PASTE: &str = "\x1b[?2004l";
+const XTERM_PASTE_START: &str = "\x1b[200~";
+const XTERM_PASTE_END: &str = "\x1b[201~";
+// Maximum length of text reported in a single `Event::Paste`;
+// the remainder of a longer paste is reported in further events.
+const MAX_PASTE_LEN: usize = 64 * 1024;
+const XTERM_ENABLE_FOCUS: &str = "\x1b[?1004h";
+const XTERM_DISABLE_FOCUS: &str = "\x1b[?1004l";
+const XTERM_FOCUS_IN: &str = "\x1b[I";
+const XTERM_FOCUS_OUT: &str = "\x1b[O";
+// Window operations requesting size reports, answered with
+// `CSI 4 ; height ; width t` and `CSI 6 ; height ; width t`, respectively
+const XTERM_QUERY_PIXEL_SIZE: &str = "\x1b[14t";
+const XTERM_QUERY_CELL_SIZE: &str = "\x1b[16t";
+const XTERM_PIXEL_SIZE_REPORT: u32 = 4;
+const XTERM_CELL_SIZE_REPORT: u32 = 6;
+// Device status report requesting the cursor position,
+// answered with `CSI line ; column R`
+const XTERM_QUERY_CURSOR: &str = "\x1b[6n";
+// Primary device attributes request, answered with `CSI ? attrs c`.
+// Sent after each query, as terminals answer requests in order; the reply
+// marks the end of any response to the query.
+const XTERM_QUERY_DEVICE_ATTRS: &str = "\x1b[c";
+// Synchronized output mode, whose support is queried with DECRQM
+const XTERM_SYNC_OUTPUT_MODE: u32 = 2026;
+const XTERM_BEGIN_SYNC_OUTPUT: &str = "\x1b[?2026h";
+const XTERM_END_SYNC_OUTPUT: &str = "\x1b[?2026l";
+
+// Saves and restores the window title and icon name on the terminal's title stack
+const XTERM_PUSH_TITLE: &str = "\x1b[22;0t";
+const XTERM_POP_TITLE: &str = "\x1b[23;0t";
+
+// Introduces a device control string
+const DCS: &str = "\x1bP";
+// Introduces an operating system command
+const OSC: &str = "\x1b]";
+// Terminates a control string
+const ST: &str = "\x1b\\";
+const BEL: &str = "\x07";
+
+// Alphabet used to encode selection data in OSC 52
+const BASE64_CHARS: &[u8; 64] =
+    b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+// Time to wait for the terminal to respond to a query
+const QUERY_TIMEOUT: Duration = Duration::from_millis(200);
+
+// Styles which cannot be removed individually
+const RESET_STYLES: Style = Style::BOLD.union(Style::DIM).union(Style::BLINK)
+    .union(Style::REVERSE).union(Style::HIDDEN);
+
+const SGR_STRIKETHROUGH: u8 = 9;
+const SGR_NO_STRIKETHROUGH: u8 = 29;
+const SGR_OVERLINE: u8 = 53;
+const SGR_NO_OVERLINE: u8 = 55;
+
+// SGR parameters selecting the target of an extended color sequence
+const SGR_EXTENDED_FG: u8 = 38;
+const SGR_EXTENDED_BG: u8 = 48;
+const SGR_UNDERLINE_COLOR: u8 = 58;
+const SGR_NO_UNDERLINE_COLOR: u8 = 59;
 
 const XTERM_SHIFT_MASK: u32 = 0x04;
 const XTERM_META_MASK: u32  = 0x08;
 const XTERM_CTRL_MASK: u32  = 0x10;
 const XTERM_MODIFIER_MASK: u32 = XTERM_SHIFT_MASK | XTERM_META_MASK | XTERM_CTRL_MASK;
+const XTERM_MOTION_MASK: u32 = 0x20;
+
+// Offset added to each value in X10 and urxvt mouse encodings
+const X10_MOUSE_OFFSET: u32 = 32;
+// X10 and urxvt encodings report a button release without the button
+const X10_MOUSE_RELEASE: u32 = 3;
+
+const XTERM_KEY_SHIFT: u32 = 0x01;
+const XTERM_KEY_ALT: u32   = 0x02;
+const XTERM_KEY_CTRL: u32  = 0x04;
+const XTERM_KEY_META: u32  = 0x08;
+const KITTY_KEY_META: u32  = 0x20;
+
+// Disambiguate escape codes, report event types, and report alternate keys
+const KITTY_KEYBOARD_FLAGS: u32 = 0x01 | 0x02 | 0x04;
+const KITTY_POP_KEYBOARD: &str = "\x1b[<u";
 
 type SeqMap = SequenceMap<SmallString<[u8; 8]>, SeqData>;
 
 #[derive(Copy, Clone)]
 enum SeqData {
     XTermMouse,
-    Key(Key),
+    X10Mouse,
+    XTermPaste,
+    FocusGained,
+    FocusLost,
+    Key(KeyEvent),
 }
 
 pub struct Terminal {
     info: Database,
+    color_depth: ColorDepth,
+    has_mouse: bool,
     out_fd: RawFd,
     in_fd: RawFd,
     owned_fd: bool,
@@ -88,6 +184,63 @@ struct Reader {
     in_buffer: Vec<u8>,
     resume: Option<Resume>,
     report_signals: SignalSet,
+    escape_timeout: Option<Duration>,
+    // Last mouse button pressed, used to report legacy release events
+    mouse_button: Option<MouseButton>,
+    click_interval: Option<Duration>,
+    clicks: ClickCounter,
+    // Window size used to convert SGR-Pixels mouse reports to cells;
+    // `Some(_)` while mouse positions are reported in pixels
+    mouse_pixels: Option<Winsize>,
+    // Time at which an incomplete sequence at the start of input expires;
+    // retained when a read times out while awaiting its remainder
+    escape_expiry: Option<Instant>,
+    // Timed out queries whose replies are yet to be received, oldest first
+    late_replies: VecDeque<LateReply>,
+    // Whether a paste is in progress, its text having exceeded `MAX_PASTE_LEN`
+    in_paste: bool,
+}
+
+// Returns the length of a response to a query at the start of input
+type ResponseParser = Box<dyn Fn(&[u8]) -> Option<usize> + Send>;
+
+// A query which timed out before the terminal replied
+struct LateReply {
+    // Position in the input buffer at which replies to the query may begin
+    start: usize,
+    parse: ResponseParser,
+}
+
+impl Reader {
+    // Removes a range of input, adjusting the positions of late replies
+    fn remove_input(&mut self, range: Range<usize>) {
+        for late in &mut self.late_replies {
+            late.start = removed_offset(late.start, &range);
+        }
+
+        let _ = self.in_buffer.drain(range);
+    }
+
+    fn process_mouse_event(&mut self, mouse: &mut MouseEvent) {
+        match mouse.input {
+            MouseInput::ButtonPressed(button) => {
+                self.mouse_button = Some(button);
+                mouse.click_count = self.clicks.click(
+                    button, mouse.position, self.click_interval);
+            }
+            // Legacy mouse encodings do not report which button was released;
+            // this is taken to be the last button pressed.
+            MouseInput::ButtonReleased(ref mut button) => {
+                if *button == MouseButton::Other(X10_MOUSE_RELEASE) {
+                    if let Some(pressed) = self.mouse_button {
+                        *button = pressed;
+                    }
+                }
+                self.mouse_button = None;
+            }
+            _ => ()
+        }
+    }
 }
 
 struct Writer {
@@ -96,15 +249,33 @@ struct Writer {
     fg: Option<Color>,
     bg: Option<Color>,
     cur_style: Style,
+    ul_style: UnderlineStyle,
+    ul_color: Option<Color>,
+    // Whether the original title has been saved on the title stack
+    title_pushed: bool,
+    // Whether a title has been written to a status line which cannot be saved
+    status_line_set: bool,
+    // URI of the currently open hyperlink
+    link: Option<String>,
+    // Whether synchronized output is supported; `None` until determined
+    sync_output: Option<bool>,
 }
 
 impl Terminal {
     fn new(in_fd: RawFd, out_fd: RawFd, owned_fd: bool) -> io::Result<Terminal> {
         let info = Database::from_env().map_err(ti_to_io)?;
+        Ok(Terminal::with_info(info, in_fd, out_fd, owned_fd))
+    }
+
+    fn with_info(info: Database, in_fd: RawFd, out_fd: RawFd, owned_fd: bool) -> Terminal {
         let sequences = sequences(&info);
+        let color_depth = color_depth(&info);
+        let has_mouse = has_mouse(&info);
 
-        Ok(Terminal{
+        Terminal{
             info,
+            color_depth,
+            has_mouse,
             in_fd,
             out_fd,
             owned_fd,
@@ -113,9 +284,17 @@ impl Terminal {
                 in_buffer: Vec::new(),
                 resume: None,
                 report_signals: SignalSet::new(),
+                escape_timeout: None,
+                mouse_button: None,
+                click_interval: None,
+                clicks: ClickCounter::new(),
+                mouse_pixels: None,
+                escape_expiry: None,
+                late_replies: VecDeque::new(),
+                in_paste: false,
             }),
             writer: Mutex::new(Writer::new()),
-        })
+        }
     }
 
     pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Terminal> {
@@ -130,6 +309,15 @@ impl Terminal {
         r
     }
 
+    // Opens a terminal device using the named terminfo entry
+    #[cfg(test)]
+    pub fn open_with_name<P: AsRef<Path>>(path: P, name: &str) -> io::Result<Terminal> {
+        let info = Database::from_name(name).map_err(ti_to_io)?;
+        let fd = open_rw(path)?;
+
+        Ok(Terminal::with_info(info, fd, fd, true))
+    }
+
     pub fn stdout() -> io::Result<Terminal> {
         Terminal::new(STDIN_FILENO, STDOUT_FILENO, false)
     }
@@ -146,6 +334,10 @@ impl Terminal {
         is_xterm(self.name())
     }
 
+    pub fn color_depth(&self) -> ColorDepth {
+        self.color_depth
+    }
+
     pub fn size(&self) -> io::Result<Size> {
         self.lock_writer().size()
     }
@@ -178,6 +370,30 @@ impl Terminal {
         self.lock_reader().restore(state)
     }
 
+    pub fn pixel_size(&self) -> io::Result<Option<PixelSize>> {
+        self.lock_reader().pixel_size()
+    }
+
+    pub fn cell_size(&self) -> io::Result<Option<PixelSize>> {
+        self.lock_reader().cell_size()
+    }
+
+    pub fn cursor_position(&self, timeout: Option<Duration>) -> io::Result<Option<Cursor>> {
+        self.lock_reader().cursor_position(timeout)
+    }
+
+    pub fn query(&self, query: &Query, timeout: Option<Duration>) -> io::Result<Option<Reply>> {
+        self.lock_reader().query(query, timeout)
+    }
+
+    pub fn background_color(&self) -> io::Result<Option<Color>> {
+        self.lock_reader().background_color()
+    }
+
+    pub fn has_sync_output(&self) -> io::Result<bool> {
+        self.lock_reader().has_sync_output()
+    }
+
     pub fn clear_screen(&self) -> io::Result<()> {
         self.lock_writer().clear_screen()
     }
@@ -226,6 +442,14 @@ impl Terminal {
         self.lock_writer().set_cursor_mode(mode)
     }
 
+    pub fn set_title(&self, title: &str) -> io::Result<()> {
+        self.lock_writer().set_title(title)
+    }
+
+    pub fn set_clipboard(&self, selection: Selection, text: &str) -> io::Result<()> {
+        self.lock_writer().set_clipboard(selection, text)
+    }
+
     pub fn write_char(&self, ch: char) -> io::Result<()> {
         self.write_str(ch.encode_utf8(&mut [0; 4]))
     }
@@ -264,6 +488,18 @@ impl Terminal {
         self.lock_writer().set_style(style)
     }
 
+    pub fn set_underline_style(&self, style: UnderlineStyle) -> io::Result<()> {
+        self.lock_writer().set_underline_style(style)
+    }
+
+    pub fn set_underline_color(&self, color: Option<Color>) -> io::Result<()> {
+        self.lock_writer().set_underline_color(color)
+    }
+
+    pub fn set_link(&self, uri: Option<&str>) -> io::Result<()> {
+        self.lock_writer().set_link(uri)
+    }
+
     pub fn set_theme(&self, theme: Theme) -> io::Result<()> {
         self.lock_writer().set_theme(theme)
     }
@@ -299,7 +535,14 @@ impl Terminal {
 
 impl Drop for Terminal {
     fn drop(&mut self) {
-        if let Err(e) = self.set_cursor_mode(CursorMode::Normal) {
+        let r = self.set_cursor_mode(CursorMode::Normal)
+            .and_then(|_| {
+                let mut writer = self.lock_writer();
+                writer.set_link(None)?;
+                writer.restore_title()
+            });
+
+        if let Err(e) = r {
             eprintln!("failed to restore terminal: {}", e);
         }
 
@@ -335,6 +578,9 @@ impl<'a> TerminalReadGuard<'a> {
             old_sigwinch: None,
             restore_keypad: false,
             restore_mouse: false,
+            restore_paste: false,
+            restore_focus: false,
+            restore_kitty_keyboard: false,
             prev_resume: self.reader.resume,
         };
 
@@ -373,9 +619,17 @@ impl<'a> TerminalReadGuard<'a> {
 
         tcsetattr(self.term.in_fd, SetArg::TCSANOW, &tio).map_err(nix_to_io)?;
 
+        self.reader.mouse_pixels = None;
+        self.reader.escape_expiry = None;
+        self.reader.in_paste = false;
+
         if config.enable_mouse {
             if writer.enable_mouse(config.always_track_motion)? {
                 state.restore_mouse = true;
+
+                if config.enable_mouse_pixels {
+                    self.reader.mouse_pixels = writer.enable_mouse_pixels()?;
+                }
             }
         }
 
@@ -385,6 +639,18 @@ impl<'a> TerminalReadGuard<'a> {
             }
         }
 
+        if config.enable_bracketed_paste && writer.enable_bracketed_paste()? {
+            state.restore_paste = true;
+        }
+
+        if config.enable_focus_events && writer.enable_focus_events()? {
+            state.restore_focus = true;
+        }
+
+        if config.enable_kitty_keyboard && writer.enable_kitty_keyboard()? {
+            state.restore_kitty_keyboard = true;
+        }
+
         writer.flush()?;
 
         let action = SigAction::new(SigHandler::Handler(handle_signal),
@@ -406,6 +672,8 @@ impl<'a> TerminalReadGuard<'a> {
         }
 
         self.reader.report_signals = config.report_signals;
+        self.reader.escape_timeout = config.escape_timeout;
+        self.reader.click_interval = config.click_interval;
         self.reader.resume = Some(Resume{config});
 
         Ok(state)
@@ -416,18 +684,282 @@ impl<'a> TerminalReadGuard<'a> {
         self.restore_with_lock(&mut writer, state)
     }
 
+    pub fn pixel_size(&mut self) -> io::Result<Option<PixelSize>> {
+        let mut writer = self.term.lock_writer();
+        self.pixel_size_with_lock(&mut writer)
+    }
+
+    pub fn pixel_size_with_lock(&mut self, writer: &mut TerminalWriteGuard)
+            -> io::Result<Option<PixelSize>> {
+        let ws = winsize(self.term.out_fd)?;
+
+        if ws.ws_xpixel != 0 && ws.ws_ypixel != 0 {
+            return Ok(Some(PixelSize{
+                width: ws.ws_xpixel as usize,
+                height: ws.ws_ypixel as usize,
+            }));
+        }
+
+        self.request(writer, XTERM_QUERY_PIXEL_SIZE, Some(QUERY_TIMEOUT),
+            |buf| parse_size_report(buf, XTERM_PIXEL_SIZE_REPORT))
+    }
+
+    pub fn cell_size(&mut self) -> io::Result<Option<PixelSize>> {
+        let mut writer = self.term.lock_writer();
+        self.cell_size_with_lock(&mut writer)
+    }
+
+    pub fn cell_size_with_lock(&mut self, writer: &mut TerminalWriteGuard)
+            -> io::Result<Option<PixelSize>> {
+        let ws = winsize(self.term.out_fd)?;
+
+        if ws.ws_xpixel != 0 && ws.ws_ypixel != 0 &&
+                ws.ws_col != 0 && ws.ws_row != 0 {
+            return Ok(Some(PixelSize{
+                width: (ws.ws_xpixel / ws.ws_col) as usize,
+                height: (ws.ws_ypixel / ws.ws_row) as usize,
+            }));
+        }
+
+        self.request(writer, XTERM_QUERY_CELL_SIZE, Some(QUERY_TIMEOUT),
+            |buf| parse_size_report(buf, XTERM_CELL_SIZE_REPORT))
+    }
+
+    pub fn cursor_position(&mut self, timeout: Option<Duration>)
+            -> io::Result<Option<Cursor>> {
+        let mut writer = self.term.lock_writer();
+        self.cursor_position_with_lock(&mut writer, timeout)
+    }
+
+    pub fn cursor_position_with_lock(&mut self, writer: &mut TerminalWriteGuard,
+            timeout: Option<Duration>) -> io::Result<Option<Cursor>> {
+        self.request(writer, XTERM_QUERY_CURSOR, timeout, parse_cursor_report)
+    }
+
+    pub fn query(&mut self, query: &Query, timeout: Option<Duration>)
+            -> io::Result<Option<Reply>> {
+        let mut writer = self.term.lock_writer();
+        self.query_with_lock(&mut writer, query, timeout)
+    }
+
+    pub fn query_with_lock(&mut self, writer: &mut TerminalWriteGuard,
+            query: &Query, timeout: Option<Duration>) -> io::Result<Option<Reply>> {
+        let req = query_request(query);
+        let query = query.clone();
+
+        self.request(writer, &req, timeout, move |buf| parse_reply(&query, buf))
+    }
+
+    pub fn background_color(&mut self) -> io::Result<Option<Color>> {
+        let mut writer = self.term.lock_writer();
+        self.background_color_with_lock(&mut writer)
+    }
+
+    pub fn background_color_with_lock(&mut self, writer: &mut TerminalWriteGuard)
+            -> io::Result<Option<Color>> {
+        match self.query_with_lock(writer, &Query::BackgroundColor, Some(QUERY_TIMEOUT))? {
+            Some(Reply::BackgroundColor(color)) => Ok(Some(color)),
+            _ => Ok(env::var("COLORFGBG").ok()
+                .and_then(|s| colorfgbg_background(&s)))
+        }
+    }
+
+    pub fn has_sync_output(&mut self) -> io::Result<bool> {
+        let mut writer = self.term.lock_writer();
+        self.has_sync_output_with_lock(&mut writer)
+    }
+
+    // Synchronized output is described by the extended capability `Sync`.
+    // Where it is absent, the terminal is asked whether it recognizes the
+    // mode. The result is retained, so that the terminal is queried once.
+    pub fn has_sync_output_with_lock(&mut self, writer: &mut TerminalWriteGuard)
+            -> io::Result<bool> {
+        if let Some(sync) = writer.writer.sync_output {
+            return Ok(sync);
+        }
+
+        let sync = self.term.info.raw("Sync").is_some() ||
+            matches!(self.query_with_lock(writer,
+                    &Query::Mode(XTERM_SYNC_OUTPUT_MODE), Some(QUERY_TIMEOUT))?,
+                Some(Reply::Mode(_, ModeStatus::Set)) |
+                Some(Reply::Mode(_, ModeStatus::Reset)));
+
+        writer.writer.sync_output = Some(sync);
+        Ok(sync)
+    }
+
+    // Writes a request to the terminal and waits for a response, which is
+    // recognized by `parse`. Input received before or after the response
+    // remains in the input buffer.
+    //
+    // The request is followed by a device attributes request. If its reply
+    // arrives first, the terminal does not support the request. If the
+    // timeout expires, both replies are removed from input when they arrive.
+    fn request<T, F>(&mut self, writer: &mut TerminalWriteGuard, query: &str,
+            timeout: Option<Duration>, parse: F) -> io::Result<Option<T>>
+            where F: Fn(&[u8]) -> Option<(T, usize)> + Send + 'static {
+        use nix::sys::termios::SpecialCharacterIndices::*;
+
+        let old_tio = match tcgetattr(self.term.in_fd) {
+            Ok(tio) => tio,
+            // Input is not a terminal
+            Err(_) => return Ok(None)
+        };
+
+        // If the terminal has not been prepared, the response would be
+        // echoed and withheld until the user enters a newline.
+        let set_tio = old_tio.local_flags.intersects(LocalFlags::ICANON | LocalFlags::ECHO);
+
+        if set_tio {
+            let mut tio = old_tio.clone();
+
+            tio.local_flags.remove(LocalFlags::ICANON | LocalFlags::ECHO);
+            tio.control_chars[VMIN as usize] = 0;
+            tio.control_chars[VTIME as usize] = 0;
+
+            tcsetattr(self.term.in_fd, SetArg::TCSANOW, &tio).map_err(nix_to_io)?;
+        }
+
+        let res = self.read_response(writer, query, timeout, parse);
+
+        if set_tio {
+            tcsetattr(self.term.in_fd, SetArg::TCSANOW, &old_tio).map_err(nix_to_io)?;
+        }
+
+        res
+    }
+
+    fn read_response<T, F>(&mut self, writer: &mut TerminalWriteGuard, query: &str,
+            timeout: Option<Duration>, parse: F) -> io::Result<Option<T>>
+            where F: Fn(&[u8]) -> Option<(T, usize)> + Send + 'static {
+        writer.write_str(query)?;
+        writer.write_str(XTERM_QUERY_DEVICE_ATTRS)?;
+        writer.flush()?;
+
+        let deadline = timeout.map(|t| Instant::now() + t);
+        let mut start = self.reader.in_buffer.len();
+        let mut value = None;
+
+        loop {
+            for range in self.discard_late_replies() {
+                start = removed_offset(start, &range);
+            }
+
+            if value.is_none() {
+                value = take_response(&mut self.reader.in_buffer, start, &parse);
+            }
+
+            if let Some((pos, len)) = find_device_attrs(&self.reader.in_buffer[start..]) {
+                self.reader.remove_input(start + pos..start + pos + len);
+                return Ok(value);
+            }
+
+            let remaining = match deadline {
+                Some(deadline) => match deadline.checked_duration_since(Instant::now()) {
+                    Some(remaining) => Some(remaining),
+                    None => break
+                },
+                None => None
+            };
+
+            // A pending signal is left to be reported by `read_event`
+            if get_signal().is_some() || !self.wait_input(remaining)? ||
+                    get_signal().is_some() {
+                break;
+            }
+
+            let mut buf = [0; 128];
+
+            match read(self.term.in_fd, &mut buf) {
+                Ok(0) => break,
+                Ok(n) => self.reader.in_buffer.extend_from_slice(&buf[..n]),
+                Err(Errno::EINTR) => (),
+                Err(e) => return Err(nix_to_io(e))
+            }
+        }
+
+        // Replies which arrive later are removed from input. If a response
+        // was already received, only the device attributes reply remains.
+        let parse: ResponseParser = if value.is_some() {
+            Box::new(|_| None)
+        } else {
+            Box::new(move |buf| parse(buf).map(|(_, len)| len))
+        };
+
+        self.reader.late_replies.push_back(LateReply{start, parse});
+
+        Ok(value)
+    }
+
+    // Removes replies to timed out queries, which are found following
+    // the position of each query in input. Other input is left in place.
+    // Returns the ranges removed, in the order of their removal.
+    fn discard_late_replies(&mut self) -> Vec<Range<usize>> {
+        let mut removed = Vec::new();
+
+        while let Some(late) = self.reader.late_replies.front() {
+            let start = late.start;
+            let buf = &self.reader.in_buffer[start..];
+
+            let (pos, len) = match find_device_attrs(buf) {
+                Some(found) => found,
+                None => break
+            };
+
+            // Find a response preceding the device attributes reply
+            let response = buf[..pos].iter().enumerate()
+                .filter(|&(_, &b)| b == b'\x1b')
+                .find_map(|(i, _)| (late.parse)(&buf[i..pos]).map(|n| start + i..start + i + n));
+
+            let _ = self.reader.late_replies.pop_front();
+
+            // The later range is removed first, leaving the earlier in place
+            let attrs = start + pos..start + pos + len;
+            self.reader.remove_input(attrs.clone());
+            removed.push(attrs);
+
+            if let Some(range) = response {
+                self.reader.remove_input(range.clone());
+                removed.push(range);
+            }
+        }
+
+        removed
+    }
+
     pub fn restore_with_lock(&mut self, writer: &mut TerminalWriteGuard,
             state: PrepareState) -> io::Result<()> {
         self.reader.resume = state.prev_resume;
+        self.reader.escape_timeout = state.prev_resume
+            .and_then(|r| r.config.escape_timeout);
+        self.reader.click_interval = state.prev_resume
+            .and_then(|r| r.config.click_interval);
 
         if state.restore_mouse {
             writer.disable_mouse()?;
+            self.reader.mouse_pixels = None;
         }
 
         if state.restore_keypad {
             writer.disable_keypad()?;
         }
 
+        if state.restore_paste {
+            writer.disable_bracketed_paste()?;
+        }
+
+        if state.restore_focus {
+            writer.disable_focus_events()?;
+        }
+
+        if state.restore_kitty_keyboard {
+            writer.disable_kitty_keyboard()?;
+        }
+
+        // An open hyperlink would otherwise apply to all later output
+        writer.set_link(None)?;
+        writer.restore_title()?;
+
         writer.flush()?;
 
         tcsetattr(self.term.in_fd, SetArg::TCSANOW, &state.old_tio.into()).map_err(nix_to_io)?;
@@ -454,11 +986,20 @@ impl<'a> TerminalReadGuard<'a> {
     }
 
     pub fn wait_event(&mut self, timeout: Option<Duration>) -> io::Result<bool> {
-        if get_signal().is_some() {
+        self.discard_late_replies();
+
+        if peek_event(&self.reader.in_buffer, &self.term.sequences,
+                self.reader.mouse_pixels.as_ref())?.is_some() {
             return Ok(true);
         }
 
-        if peek_event(&self.reader.in_buffer, &self.term.sequences)?.is_some() {
+        self.wait_input(timeout)
+    }
+
+    // Waits for a signal or for input to become available,
+    // regardless of the contents of the input buffer.
+    fn wait_input(&mut self, timeout: Option<Duration>) -> io::Result<bool> {
+        if get_signal().is_some() {
             return Ok(true);
         }
 
@@ -490,12 +1031,17 @@ impl<'a> TerminalReadGuard<'a> {
     }
 
     pub fn read_event(&mut self, timeout: Option<Duration>) -> io::Result<Option<Event>> {
-        if let Some(ev) = self.try_read()? {
+        let deadline = timeout.map(|t| Instant::now() + t);
+
+        if let Some(ev) = self.try_read(deadline)? {
             return Ok(Some(ev));
         }
 
+        let timeout = deadline.map(|deadline| deadline.checked_duration_since(Instant::now())
+            .unwrap_or_else(|| Duration::from_secs(0)));
+
         match self.read_into_buffer(timeout)? {
-            Some(Event::Raw(_)) => self.try_read(),
+            Some(Event::Raw(_)) => self.try_read(deadline),
             Some(Event::Signal(sig)) => {
                 if let Some(ev) = self.handle_signal(sig)? {
                     Ok(Some(ev))
@@ -508,11 +1054,13 @@ impl<'a> TerminalReadGuard<'a> {
     }
 
     pub fn read_raw(&mut self, buf: &mut [u8], timeout: Option<Duration>) -> io::Result<Option<Event>> {
+        self.discard_late_replies();
+
         if !self.reader.in_buffer.is_empty() {
             let n = buf.len().min(self.reader.in_buffer.len());
             buf[..n].copy_from_slice(&self.reader.in_buffer[..n]);
 
-            let _ = self.reader.in_buffer.drain(..n);
+            self.reader.remove_input(0..n);
 
             return Ok(Some(Event::Raw(n)));
         }
@@ -562,7 +1110,7 @@ impl<'a> TerminalReadGuard<'a> {
             return Ok(Some(Event::Signal(sig)));
         }
 
-        if !self.wait_event(timeout)? {
+        if !self.wait_input(timeout)? {
             return Ok(None);
         }
 
@@ -584,17 +1132,87 @@ impl<'a> TerminalReadGuard<'a> {
         }
     }
 
-    fn try_read(&mut self) -> io::Result<Option<Event>> {
-        let in_buffer = &mut self.reader.in_buffer;
+    // Reads an event from the input buffer. When an escape timeout is set,
+    // waits no later than `deadline` for the remainder of a sequence.
+    fn try_read(&mut self, deadline: Option<Instant>) -> io::Result<Option<Event>> {
+        self.discard_late_replies();
+
+        // Whether an incomplete sequence was not completed within the timeout
+        let mut expired = false;
+
+        if let Some(timeout) = self.reader.escape_timeout {
+            // Wait for the remainder of an incomplete sequence.
+            // Each time more input arrives, the timeout begins again.
+            while is_incomplete(&self.reader.in_buffer, &self.term.sequences) {
+                let now = Instant::now();
+                let expiry = *self.reader.escape_expiry.get_or_insert(now + timeout);
+
+                if expiry <= now {
+                    expired = true;
+                    break;
+                }
+
+                // If the caller's timeout ends first, the sequence is
+                // awaited further by the next read.
+                let end = match deadline {
+                    Some(deadline) if deadline < expiry => deadline,
+                    _ => expiry
+                };
+                let wait = end.checked_duration_since(now)
+                    .unwrap_or_else(|| Duration::from_secs(0));
+
+                match self.read_into_buffer(Some(wait))? {
+                    Some(Event::Raw(n)) if n != 0 => self.reader.escape_expiry = None,
+                    Some(Event::Signal(sig)) => {
+                        if let Some(ev) = self.handle_signal(sig)? {
+                            return Ok(Some(ev));
+                        }
+                    }
+                    _ if end < expiry => return Ok(None),
+                    _ => {
+                        expired = true;
+                        break;
+                    }
+                }
+            }
+
+            self.reader.escape_expiry = None;
+        }
+
+        let pixels = self.reader.mouse_pixels;
+        let in_buffer = &self.reader.in_buffer;
 
         if in_buffer.is_empty() {
             Ok(None)
+        } else if self.reader.in_paste {
+            // Continue a paste too long to be reported in a single event
+            match parse_paste_data(in_buffer) {
+                Some((text, n)) => {
+                    let done = in_buffer[..n].ends_with(XTERM_PASTE_END.as_bytes());
+                    self.reader.remove_input(0..n);
+                    self.reader.in_paste = !done;
+                    Ok(Some(Event::Paste(text)))
+                }
+                None => Ok(None)
+            }
         } else {
-            match peek_event(&in_buffer, &self.term.sequences) {
-                Ok(Some((ev, n))) => {
-                    let _ = in_buffer.drain(..n);
+            match peek_event(in_buffer, &self.term.sequences, pixels.as_ref()) {
+                Ok(Some((mut ev, n))) => {
+                    let done = in_buffer[..n].ends_with(XTERM_PASTE_END.as_bytes());
+                    self.reader.remove_input(0..n);
+                    match ev {
+                        Event::Mouse(ref mut mouse) => self.reader.process_mouse_event(mouse),
+                        Event::Paste(_) => self.reader.in_paste = !done,
+                        _ => ()
+                    }
                     Ok(Some(ev))
                 }
+                // The remainder of a control sequence did not arrive in time;
+                // its leading ESC is reported as the Escape key.
+                Ok(None) if expired && in_buffer.starts_with(b"\x1b") => {
+                    self.reader.remove_input(0..1);
+                    Ok(Some(Event::Key(Key::Escape)))
+                }
                 Ok(None) => Ok(None),
                 Err(e) => Err(e)
             }
@@ -607,6 +1225,9 @@ impl<'a> TerminalReadGuard<'a> {
                 self.resume()?;
             }
             Signal::Resize => {
+                if self.reader.mouse_pixels.is_some() {
+                    self.reader.mouse_pixels = Some(winsize(self.term.out_fd)?);
+                }
                 let size = self.term.size()?;
                 return Ok(Some(Event::Resize(size)));
             }
@@ -662,6 +1283,21 @@ macro_rules! expand_req {
     } }
 }
 
+// Expands a capability which accepts a count parameter, if present;
+// otherwise, expands the equivalent single-step capability `n` times.
+macro_rules! expand_count {
+    ( $slf:expr , $n:expr , $parm:path , $single:path , $name:expr ) => { {
+        let n = $n;
+
+        if (n == 1 && $slf.term.info.get::<$single>().is_some()) ||
+                $slf.term.info.get::<$parm>().is_none() {
+            (0..n).try_for_each(|_| expand_req!($slf, $single, $name))
+        } else {
+            expand_opt!($slf, $parm, |ex| ex.parameters(to_u32(n)))
+        }
+    } }
+}
+
 impl<'a> TerminalWriteGuard<'a> {
     fn new(term: &'a Terminal, writer: MutexGuard<'a, Writer>) -> TerminalWriteGuard<'a> {
         TerminalWriteGuard{term, writer}
@@ -692,8 +1328,22 @@ impl<'a> TerminalWriteGuard<'a> {
         self.write_bytes(XTERM_DISABLE_MOUSE_MOTION.as_bytes())
     }
 
+    // Returns the window size used to convert pixel positions, if enabled
+    fn enable_mouse_pixels(&mut self) -> io::Result<Option<Winsize>> {
+        // Pixel positions cannot be converted to cells
+        // unless the terminal reports its size in pixels.
+        let ws = winsize(self.term.out_fd)?;
+
+        if ws.ws_xpixel != 0 && ws.ws_ypixel != 0 {
+            self.write_bytes(XTERM_ENABLE_MOUSE_PIXELS.as_bytes())?;
+            Ok(Some(ws))
+        } else {
+            Ok(None)
+        }
+    }
+
     fn enable_mouse(&mut self, track_motion: bool) -> io::Result<bool> {
-        if self.term.is_xterm() {
+        if self.term.has_mouse {
             self.write_bytes(XTERM_ENABLE_MOUSE.as_bytes())?;
             if track_motion {
                 self.write_bytes(XTERM_ENABLE_MOUSE_MOTION.as_bytes())?;
@@ -704,6 +1354,50 @@ impl<'a> TerminalWriteGuard<'a> {
         }
     }
 
+    fn disable_bracketed_paste(&mut self) -> io::Result<()> {
+        self.write_bytes(XTERM_DISABLE_PASTE.as_bytes())
+    }
+
+    fn enable_bracketed_paste(&mut self) -> io::Result<bool> {
+        if self.term.is_xterm() {
+            self.write_bytes(XTERM_ENABLE_PASTE.as_bytes())?;
+            Ok(true)
+        } else {
+            Ok(false)
+        }
+    }
+
+    fn disable_focus_events(&mut self) -> io::Result<()> {
+        self.write_bytes(XTERM_DISABLE_FOCUS.as_bytes())
+    }
+
+    fn enable_focus_events(&mut self) -> io::Result<bool> {
+        if self.term.is_xterm() {
+            self.write_bytes(XTERM_ENABLE_FOCUS.as_bytes())?;
+            Ok(true)
+        } else {
+            Ok(false)
+        }
+    }
+
+    fn disable_kitty_keyboard(&mut self) -> io::Result<()> {
+        self.write_bytes(KITTY_POP_KEYBOARD.as_bytes())
+    }
+
+    fn enable_kitty_keyboard(&mut self) -> io::Result<bool> {
+        use std::fmt::Write;
+
+        // Terminals which do not support the protocol ignore this sequence
+        if self.term.is_xterm() {
+            let mut seq = SmallString::<[u8; 8]>::new();
+            let _ = write!(seq, "\x1b[>{}u", KITTY_KEYBOARD_FLAGS);
+            self.write_str(&seq)?;
+            Ok(true)
+        } else {
+            Ok(false)
+        }
+    }
+
     fn enter_screen(&mut self) -> io::Result<()> {
         match (self.term.info.get::<cap::EnterCaMode>(),
                 self.term.info.get::<cap::ChangeScrollRegion>(),
@@ -740,17 +1434,23 @@ impl<'a> TerminalWriteGuard<'a> {
 
     pub fn clear_attributes(&mut self) -> io::Result<()> {
         if self.writer.fg.is_some() || self.writer.bg.is_some() ||
+                self.writer.ul_color.is_some() ||
                 !self.writer.cur_style.is_empty() {
             self.writer.fg = None;
             self.writer.bg = None;
+            self.writer.ul_color = None;
             self.writer.cur_style = Style::empty();
             expand_opt!(self, cap::ExitAttributeMode)?;
         }
 
+        self.writer.ul_style = UnderlineStyle::Single;
+
         Ok(())
     }
 
     pub fn set_fg(&mut self, fg: Option<Color>) -> io::Result<()> {
+        let fg = fg.map(|c| c.quantize(self.term.color_depth));
+
         if self.writer.fg == fg {
             Ok(())
         } else {
@@ -766,6 +1466,8 @@ impl<'a> TerminalWriteGuard<'a> {
     }
 
     pub fn set_bg(&mut self, bg: Option<Color>) -> io::Result<()> {
+        let bg = bg.map(|c| c.quantize(self.term.color_depth));
+
         if self.writer.bg == bg {
             Ok(())
         } else {
@@ -793,7 +1495,22 @@ impl<'a> TerminalWriteGuard<'a> {
             expand_opt!(self, cap::EnterReverseMode)?;
         }
         if add.contains(Style::UNDERLINE) {
-            expand_opt!(self, cap::EnterUnderlineMode)?;
+            self.enter_underline()?;
+        }
+        if add.contains(Style::DIM) {
+            expand_opt!(self, cap::EnterDimMode)?;
+        }
+        if add.contains(Style::BLINK) {
+            expand_opt!(self, cap::EnterBlinkMode)?;
+        }
+        if add.contains(Style::HIDDEN) {
+            expand_opt!(self, cap::EnterSecureMode)?;
+        }
+        if add.contains(Style::STRIKETHROUGH) {
+            self.expand_ext("smxx", SGR_STRIKETHROUGH)?;
+        }
+        if add.contains(Style::OVERLINE) {
+            self.expand_ext("Smol", SGR_OVERLINE)?;
         }
 
         self.writer.cur_style |= add;
@@ -804,16 +1521,11 @@ impl<'a> TerminalWriteGuard<'a> {
     pub fn remove_style(&mut self, style: Style) -> io::Result<()> {
         let remove = style & self.writer.cur_style;
 
-        if remove.intersects(Style::BOLD | Style::REVERSE) {
-            // terminfo does not contain entries to remove bold or reverse.
-            // Instead, we must reset all attributes.
+        if remove.intersects(RESET_STYLES) {
+            // terminfo does not contain entries to remove bold, dim, blink,
+            // reverse, or hidden. Instead, we must reset all attributes.
             let new_style = self.writer.cur_style - remove;
-            let fg = self.writer.fg;
-            let bg = self.writer.bg;
-            self.clear_attributes()?;
-            self.add_style(new_style)?;
-            self.set_fg(fg)?;
-            self.set_bg(bg)?;
+            self.reset_style(new_style)?;
         } else {
             if remove.contains(Style::ITALIC) {
                 expand_opt!(self, cap::ExitItalicsMode)?;
@@ -821,6 +1533,12 @@ impl<'a> TerminalWriteGuard<'a> {
             if remove.contains(Style::UNDERLINE) {
                 expand_opt!(self, cap::ExitUnderlineMode)?;
             }
+            if remove.contains(Style::STRIKETHROUGH) {
+                self.expand_ext("rmxx", SGR_NO_STRIKETHROUGH)?;
+            }
+            if remove.contains(Style::OVERLINE) {
+                self.expand_ext("Rmol", SGR_NO_OVERLINE)?;
+            }
 
             self.writer.cur_style -= remove;
         }
@@ -832,15 +1550,10 @@ impl<'a> TerminalWriteGuard<'a> {
         let add = style - self.writer.cur_style;
         let remove = self.writer.cur_style - style;
 
-        if remove.intersects(Style::BOLD | Style::REVERSE) {
-            // terminfo does not contain entries to remove bold or reverse.
-            // Instead, we must reset all attributes.
-            let fg = self.writer.fg;
-            let bg = self.writer.bg;
-            self.clear_attributes()?;
-            self.set_fg(fg)?;
-            self.set_bg(bg)?;
-            self.add_style(style)?;
+        if remove.intersects(RESET_STYLES) {
+            // terminfo does not contain entries to remove bold, dim, blink,
+            // reverse, or hidden. Instead, we must reset all attributes.
+            self.reset_style(style)?;
         } else {
             self.add_style(add)?;
             self.remove_style(remove)?;
@@ -849,8 +1562,64 @@ impl<'a> TerminalWriteGuard<'a> {
         Ok(())
     }
 
+    pub fn set_underline_style(&mut self, style: UnderlineStyle) -> io::Result<()> {
+        if self.writer.ul_style != style {
+            self.writer.ul_style = style;
+
+            if self.writer.cur_style.contains(Style::UNDERLINE) {
+                self.enter_underline()?;
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn set_underline_color(&mut self, color: Option<Color>) -> io::Result<()> {
+        let color = color.map(|c| c.quantize(self.term.color_depth));
+
+        if self.writer.ul_color != color {
+            self.set_ul_color(color)?;
+            self.writer.ul_color = color;
+        }
+
+        Ok(())
+    }
+
+    pub fn set_link(&mut self, uri: Option<&str>) -> io::Result<()> {
+        if !self.term.is_xterm() || self.writer.link.as_deref() == uri {
+            return Ok(());
+        }
+
+        self.write_str(OSC)?;
+        self.write_str("8;;")?;
+
+        if let Some(uri) = uri {
+            // Control characters would terminate the sequence
+            let uri = uri.chars()
+                .filter(|&ch| !ch.is_control())
+                .collect::<String>();
+            self.write_str(&uri)?;
+        }
+
+        self.write_str(BEL)?;
+        self.writer.link = uri.map(|s| s.to_owned());
+
+        Ok(())
+    }
+
     pub fn set_theme(&mut self, theme: Theme) -> io::Result<()> {
-        self.set_attrs(theme.fg, theme.bg, theme.style)
+        if (self.writer.fg.is_some() && theme.fg.is_none()) ||
+                (self.writer.bg.is_some() && theme.bg.is_none()) {
+            self.clear_attributes()?;
+        }
+
+        self.set_underline_style(theme.underline)?;
+        self.set_style(theme.style)?;
+        self.set_fg(theme.fg)?;
+        self.set_bg(theme.bg)?;
+        self.set_underline_color(theme.underline_color)?;
+
+        Ok(())
     }
 
     pub fn set_attrs(&mut self, fg: Option<Color>, bg: Option<Color>, style: Style) -> io::Result<()> {
@@ -885,13 +1654,142 @@ impl<'a> TerminalWriteGuard<'a> {
     }
 
     fn set_fg_color(&mut self, fg: Color) -> io::Result<()> {
-        expand_opt!(self, cap::SetAForeground,
-            |ex| ex.parameters(color_code(fg)))
+        match fg {
+            Color::Rgb(r, g, b) => self.write_rgb(SGR_EXTENDED_FG, r, g, b),
+            _ => expand_opt!(self, cap::SetAForeground,
+                |ex| ex.parameters(color_code(fg)))
+        }
     }
 
     fn set_bg_color(&mut self, bg: Color) -> io::Result<()> {
-        expand_opt!(self, cap::SetABackground,
-            |ex| ex.parameters(color_code(bg)))
+        match bg {
+            Color::Rgb(r, g, b) => self.write_rgb(SGR_EXTENDED_BG, r, g, b),
+            _ => expand_opt!(self, cap::SetABackground,
+                |ex| ex.parameters(color_code(bg)))
+        }
+    }
+
+    // Resets all attributes, then applies the given style.
+    // Colors and underline style are preserved.
+    fn reset_style(&mut self, style: Style) -> io::Result<()> {
+        let fg = self.writer.fg;
+        let bg = self.writer.bg;
+        let ul_style = self.writer.ul_style;
+        let ul_color = self.writer.ul_color;
+
+        self.clear_attributes()?;
+        self.writer.ul_style = ul_style;
+
+        self.set_fg(fg)?;
+        self.set_bg(bg)?;
+        self.set_underline_color(ul_color)?;
+        self.add_style(style)
+    }
+
+    fn enter_underline(&mut self) -> io::Result<()> {
+        let code: i32 = match self.writer.ul_style {
+            UnderlineStyle::Single =>
+                return expand_opt!(self, cap::EnterUnderlineMode),
+            UnderlineStyle::Double => 2,
+            UnderlineStyle::Curly => 3,
+            UnderlineStyle::Dotted => 4,
+            UnderlineStyle::Dashed => 5,
+        };
+
+        // Underline styles are described by the extended capability `Smulx`.
+        // If it is not present, xterm-compatible terminals are sent
+        // the SGR sequence and others receive a single underline.
+        match self.term.info.raw("Smulx") {
+            Some(Value::String(s)) => {
+                let writer = &mut *self.writer;
+                s.expand(&mut writer.out_buffer,
+                        &[Parameter::from(code)], &mut writer.context)
+                    .map_err(ti_to_io)
+            }
+            _ if self.term.is_xterm() => {
+                use std::fmt::Write;
+
+                let mut seq = SmallString::<[u8; 8]>::new();
+                let _ = write!(seq, "\x1b[4:{}m", code);
+                self.write_str(&seq)
+            }
+            _ => expand_opt!(self, cap::EnterUnderlineMode)
+        }
+    }
+
+    fn set_ul_color(&mut self, color: Option<Color>) -> io::Result<()> {
+        use std::fmt::Write;
+
+        // Underline color is not described by standard terminfo capabilities.
+        // The extended capability `Setulc` sets an RGB color, given as
+        // a single parameter `0xRRGGBB`.
+        let setulc = match self.term.info.raw("Setulc") {
+            Some(Value::String(s)) => Some(s),
+            _ => None
+        };
+
+        if let (Some(s), Some(Color::Rgb(r, g, b))) = (setulc, color) {
+            let rgb = (r as i32) << 16 | (g as i32) << 8 | b as i32;
+            let writer = &mut *self.writer;
+            let mut buf = Vec::new();
+
+            // Some entries, such as that of kitty, contain a stray `%;`
+            // which cannot be expanded; the SGR sequence is then used instead.
+            if s.expand(&mut buf, &[Parameter::from(rgb)], &mut writer.context).is_ok() {
+                writer.out_buffer.extend_from_slice(&buf);
+                return Ok(());
+            }
+        }
+
+        // Other colors are sent as SGR sequences to terminals having `Setulc`,
+        // as well as to xterm-compatible terminals.
+        if setulc.is_none() && !self.term.is_xterm() {
+            return Ok(());
+        }
+
+        let mut seq = SmallString::<[u8; 24]>::new();
+
+        let _ = match color {
+            Some(Color::Rgb(r, g, b)) =>
+                write!(seq, "\x1b[{}:2::{}:{}:{}m", SGR_UNDERLINE_COLOR, r, g, b),
+            Some(color) =>
+                write!(seq, "\x1b[{}:5:{}m", SGR_UNDERLINE_COLOR, color_code(color)),
+            None => write!(seq, "\x1b[{}m", SGR_NO_UNDERLINE_COLOR),
+        };
+
+        self.write_str(&seq)
+    }
+
+    // Writes an extended terminfo capability, which takes no parameters.
+    // If the capability is not present, xterm-compatible terminals
+    // are sent the equivalent SGR sequence.
+    fn expand_ext(&mut self, name: &str, sgr: u8) -> io::Result<()> {
+        match self.term.info.raw(name) {
+            Some(Value::String(s)) => {
+                let writer = &mut *self.writer;
+                s.expand(&mut writer.out_buffer, &[], &mut writer.context)
+                    .map_err(ti_to_io)
+            }
+            _ if self.term.is_xterm() => {
+                use std::fmt::Write;
+
+                let mut seq = SmallString::<[u8; 8]>::new();
+                let _ = write!(seq, "\x1b[{}m", sgr);
+                self.write_str(&seq)
+            }
+            _ => Ok(())
+        }
+    }
+
+    fn write_rgb(&mut self, target: u8, r: u8, g: u8, b: u8) -> io::Result<()> {
+        use std::fmt::Write;
+
+        // terminfo has no standard capability for direct colors,
+        // so the SGR sequence is written directly.
+        let mut seq = SmallString::<[u8; 24]>::new();
+        let _ = write!(seq, "\x1b[{};2;{};{};{}m", target, r, g, b);
+
+        self.write_str(&seq)
     }
 
     pub fn clear_screen(&mut self) -> io::Result<()> {
@@ -906,6 +1804,46 @@ impl<'a> TerminalWriteGuard<'a> {
         expand_req!(self, cap::ClrEos, "clr_eos")
     }
 
+    // Erases `n` characters, beginning at the cursor, without moving the cursor
+    pub fn erase_chars(&mut self, n: usize) -> io::Result<()> {
+        expand_req!(self, cap::EraseChars, "erase_chars",
+            |ex| ex.parameters(to_u32(n)))
+    }
+
+    // Writes an ASCII character `n` times
+    pub fn repeat_char(&mut self, ch: char, n: usize) -> io::Result<()> {
+        // `RepeatChar` is not defined by terminfo with parameters
+        match self.term.info.raw("rep") {
+            Some(Value::String(s)) if ch.is_ascii() => {
+                let writer = &mut *self.writer;
+                s.expand(&mut writer.out_buffer,
+                        &[Parameter::from(ch as u8), Parameter::from(to_u32(n))],
+                        &mut writer.context)
+                    .map_err(ti_to_io)
+            }
+            _ => Err(not_supported("repeat_char"))
+        }
+    }
+
+    // Returns whether erased cells are filled with the current background color
+    pub fn has_back_color_erase(&self) -> bool {
+        matches!(self.term.info.get::<cap::BackColorErase>(), Some(cap::BackColorErase(true)))
+    }
+
+    // Returns the number of bytes written by `f`, or `None` if it fails.
+    // The output itself is discarded.
+    //
+    // `f` must only expand capabilities, as other writes may flush the buffer.
+    pub fn measure<F>(&mut self, f: F) -> Option<usize>
+            where F: FnOnce(&mut Self) -> io::Result<()> {
+        let start = self.writer.out_buffer.len();
+        let res = f(self);
+        let len = self.writer.out_buffer.len() - start;
+
+        self.writer.out_buffer.truncate(start);
+        res.ok().map(|_| len)
+    }
+
     pub fn move_up(&mut self, n: usize) -> io::Result<()> {
         if n == 1 {
             expand_req!(self, cap::CursorUp, "cursor_up")?;
@@ -950,6 +1888,16 @@ impl<'a> TerminalWriteGuard<'a> {
         self.write_bytes(b"\r")
     }
 
+    pub fn move_to_line(&mut self, line: usize) -> io::Result<()> {
+        expand_req!(self, cap::RowAddress, "row_address",
+            |ex| ex.parameters(to_u32(line)))
+    }
+
+    pub fn move_to_column(&mut self, column: usize) -> io::Result<()> {
+        expand_req!(self, cap::ColumnAddress, "column_address",
+            |ex| ex.parameters(to_u32(column)))
+    }
+
     pub fn move_cursor(&mut self, pos: Cursor) -> io::Result<()> {
         match (self.term.info.get::<cap::CursorAddress>(),
                 self.term.info.get::<cap::CursorHome>()) {
@@ -966,6 +1914,85 @@ impl<'a> TerminalWriteGuard<'a> {
         Ok(())
     }
 
+    // Moves the lines described by `scroll` using either a scroll region
+    // or line insertion and deletion. Returns `Ok(false)` if the terminal
+    // supports neither.
+    pub fn scroll_lines(&mut self, scroll: &Scroll, lines: usize) -> io::Result<bool> {
+        let info = &self.term.info;
+        let n = scroll.n.unsigned_abs();
+
+        let full_screen = scroll.top == 0 && scroll.bottom == lines;
+
+        let can_index = if scroll.n > 0 {
+            info.get::<cap::ParmIndex>().is_some() ||
+                info.get::<cap::ScrollForward>().is_some()
+        } else {
+            info.get::<cap::ParmRindex>().is_some() ||
+                info.get::<cap::ScrollReverse>().is_some()
+        };
+
+        let can_insert =
+            (info.get::<cap::ParmInsertLine>().is_some() ||
+                info.get::<cap::InsertLine>().is_some()) &&
+            (info.get::<cap::ParmDeleteLine>().is_some() ||
+                info.get::<cap::DeleteLine>().is_some());
+
+        if can_index && (full_screen || info.get::<cap::ChangeScrollRegion>().is_some()) {
+            if !full_screen {
+                self.set_scroll_region(scroll.top, scroll.bottom)?;
+            }
+
+            if scroll.n > 0 {
+                self.move_cursor(Cursor{line: scroll.bottom - 1, column: 0})?;
+                expand_count!(self, n, cap::ParmIndex, cap::ScrollForward, "scroll_forward")?;
+            } else {
+                self.move_cursor(Cursor{line: scroll.top, column: 0})?;
+                expand_count!(self, n, cap::ParmRindex, cap::ScrollReverse, "scroll_reverse")?;
+            }
+
+            if !full_screen {
+                self.set_scroll_region(0, lines)?;
+            }
+        } else if can_insert {
+            // Lines deleted from or inserted into the region are balanced
+            // so that lines below the region do not move.
+            if scroll.n > 0 {
+                self.move_cursor(Cursor{line: scroll.top, column: 0})?;
+                self.delete_lines(n)?;
+
+                if scroll.bottom < lines {
+                    self.move_cursor(Cursor{line: scroll.bottom - n, column: 0})?;
+                    self.insert_lines(n)?;
+                }
+            } else {
+                if scroll.bottom < lines {
+                    self.move_cursor(Cursor{line: scroll.bottom - n, column: 0})?;
+                    self.delete_lines(n)?;
+                }
+
+                self.move_cursor(Cursor{line: scroll.top, column: 0})?;
+                self.insert_lines(n)?;
+            }
+        } else {
+            return Ok(false);
+        }
+
+        Ok(true)
+    }
+
+    fn set_scroll_region(&mut self, top: usize, bottom: usize) -> io::Result<()> {
+        expand_req!(self, cap::ChangeScrollRegion, "change_scroll_region",
+            |ex| ex.parameters(to_u32(top), to_u32(bottom - 1)))
+    }
+
+    fn insert_lines(&mut self, n: usize) -> io::Result<()> {
+        expand_count!(self, n, cap::ParmInsertLine, cap::InsertLine, "insert_line")
+    }
+
+    fn delete_lines(&mut self, n: usize) -> io::Result<()> {
+        expand_count!(self, n, cap::ParmDeleteLine, cap::DeleteLine, "delete_line")
+    }
+
     pub fn set_cursor_mode(&mut self, mode: CursorMode) -> io::Result<()> {
         match mode {
             CursorMode::Normal | CursorMode::Overwrite => {
@@ -982,6 +2009,109 @@ impl<'a> TerminalWriteGuard<'a> {
         Ok(())
     }
 
+    pub fn set_title(&mut self, title: &str) -> io::Result<()> {
+        let has_status_line = self.term.info.get::<cap::ToStatusLine>().is_some() &&
+            self.term.info.get::<cap::FromStatusLine>().is_some();
+
+        if !has_status_line && !self.term.is_xterm() {
+            return Ok(());
+        }
+
+        if self.term.is_xterm() && !self.writer.title_pushed {
+            self.write_str(XTERM_PUSH_TITLE)?;
+            self.writer.title_pushed = true;
+        }
+
+        if has_status_line && !self.writer.title_pushed {
+            self.writer.status_line_set = true;
+        }
+
+        // Control characters would terminate the sequence
+        let title = title.chars()
+            .filter(|&ch| !ch.is_control())
+            .collect::<String>();
+
+        if has_status_line {
+            expand_opt!(self, cap::ToStatusLine)?;
+            self.write_str(&title)?;
+            expand_opt!(self, cap::FromStatusLine)
+        } else {
+            // OSC 0 sets both the icon name and the window title
+            self.write_str(OSC)?;
+            self.write_str("0;")?;
+            self.write_str(&title)?;
+            self.write_str(BEL)
+        }
+    }
+
+    pub fn set_clipboard(&mut self, selection: Selection, text: &str) -> io::Result<()> {
+        let target = selection_name(selection);
+        let data = base64_encode(text.as_bytes());
+
+        // Setting a selection is described by the extended capability `Ms`.
+        // If it is not present, xterm-compatible terminals are sent OSC 52.
+        match self.term.info.raw("Ms") {
+            Some(Value::String(s)) => {
+                let writer = &mut *self.writer;
+                s.expand(&mut writer.out_buffer,
+                        &[Parameter::from(target), Parameter::from(data)],
+                        &mut writer.context)
+                    .map_err(ti_to_io)
+            }
+            _ if self.term.is_xterm() => {
+                self.write_str(OSC)?;
+                self.write_str("52;")?;
+                self.write_str(target)?;
+                self.write_str(";")?;
+                self.write_str(&data)?;
+                self.write_str(BEL)
+            }
+            _ => Ok(())
+        }
+    }
+
+    pub fn begin_sync_output(&mut self) -> io::Result<()> {
+        self.sync_output(1)
+    }
+
+    pub fn end_sync_output(&mut self) -> io::Result<()> {
+        self.sync_output(2)
+    }
+
+    // `Sync` takes a parameter of `1` to begin and `2` to end an update.
+    // Otherwise, the mode is used if the terminal reported recognizing it.
+    fn sync_output(&mut self, param: i32) -> io::Result<()> {
+        match self.term.info.raw("Sync") {
+            Some(Value::String(s)) => {
+                let writer = &mut *self.writer;
+                s.expand(&mut writer.out_buffer,
+                        &[Parameter::from(param)], &mut writer.context)
+                    .map_err(ti_to_io)
+            }
+            _ if self.writer.sync_output == Some(true) => {
+                if param == 1 {
+                    self.write_str(XTERM_BEGIN_SYNC_OUTPUT)
+                } else {
+                    self.write_str(XTERM_END_SYNC_OUTPUT)
+                }
+            }
+            _ => Ok(())
+        }
+    }
+
+    fn restore_title(&mut self) -> io::Result<()> {
+        if self.writer.title_pushed {
+            self.write_str(XTERM_POP_TITLE)?;
+            self.writer.title_pushed = false;
+        } else if self.writer.status_line_set {
+            // The original contents of a status line cannot be saved;
+            // the best that can be done is to clear it.
+            expand_opt!(self, cap::DisStatusLine)?;
+        }
+        self.writer.status_line_set = false;
+        Ok(())
+    }
+
     pub fn write_char(&mut self, ch: char) -> io::Result<()> {
         self.write_str(ch.encode_utf8(&mut [0; 4]))
     }
@@ -1062,6 +2192,12 @@ impl Writer {
             fg: None,
             bg: None,
             cur_style: Style::empty(),
+            ul_style: UnderlineStyle::Single,
+            ul_color: None,
+            title_pushed: false,
+            status_line_set: false,
+            link: None,
+            sync_output: None,
         }
     }
 }
@@ -1078,7 +2214,8 @@ fn sequences(info: &Database) -> SeqMap {
         ( $seq:ty , $key:expr ) => { {
             if let Some(seq) = info.get::<$seq>() {
                 if let Some(s) = ascii_str(seq.as_ref()) {
-                    sequences.insert(s.into(), SeqData::Key($key));
+                    sequences.insert(s.into(), SeqData::Key(
+                        KeyEvent::new($key, ModifierState::empty())));
                 }
             }
         } }
@@ -1090,6 +2227,8 @@ fn sequences(info: &Database) -> SeqMap {
     add!(cap::KeyRight,     Key::Right);
     add!(cap::KeyHome,      Key::Home);
     add!(cap::KeyEnd,       Key::End);
+    add!(cap::KeyBeg,       Key::Begin);
+    add!(cap::KeyB2,        Key::Begin);
     add!(cap::KeyNPage,     Key::PageDown);
     add!(cap::KeyPPage,     Key::PageUp);
     add!(cap::KeyDc,        Key::Delete);
@@ -1107,13 +2246,82 @@ fn sequences(info: &Database) -> SeqMap {
     add!(cap::KeyF11,       Key::F(11));
     add!(cap::KeyF12,       Key::F(12));
 
-    if is_xterm(info.name()) {
+    if let Some(seq) = info.get::<cap::KeyBTab>() {
+        if let Some(s) = ascii_str(seq.as_ref()) {
+            sequences.insert(s.into(), SeqData::Key(
+                KeyEvent::new(Key::Tab, ModifierState::SHIFT)));
+        }
+    }
+
+    // Keys with modifiers are described by extended capabilities,
+    // e.g. `kLFT5` is Ctrl-Left. The numeric suffix is an xterm modifier
+    // parameter; a capability without a suffix indicates Shift.
+    let mod_keys = [
+        ("kUP", Key::Up), ("kDN", Key::Down),
+        ("kLFT", Key::Left), ("kRIT", Key::Right),
+        ("kHOM", Key::Home), ("kEND", Key::End), ("kBEG", Key::Begin),
+        ("kNXT", Key::PageDown), ("kPRV", Key::PageUp),
+        ("kIC", Key::Insert), ("kDC", Key::Delete),
+    ];
+
+    for &(name, key) in &mod_keys {
+        for param in 2..=8 {
+            let cap_name = if param == 2 {
+                name.to_owned()
+            } else {
+                format!("{}{}", name, param)
+            };
+
+            if let Some(Value::String(seq)) = info.raw(&cap_name) {
+                if let Some(s) = ascii_str(seq) {
+                    sequences.insert(s.into(), SeqData::Key(
+                        KeyEvent::new(key, xterm_key_modifiers(param))));
+                }
+            }
+        }
+    }
+
+    if has_mouse(info) {
         sequences.insert(XTERM_MOUSE_INTRO.into(), SeqData::XTermMouse);
+        sequences.insert(X10_MOUSE_INTRO.into(), SeqData::X10Mouse);
+    }
+
+    if is_xterm(info.name()) {
+        sequences.insert(XTERM_PASTE_START.into(), SeqData::XTermPaste);
+        sequences.insert(XTERM_FOCUS_IN.into(), SeqData::FocusGained);
+        sequences.insert(XTERM_FOCUS_OUT.into(), SeqData::FocusLost);
     }
 
     sequences
 }
 
+// Mouse support is indicated by the `kmous` capability, which describes
+// the prefix of mouse event sequences, or the `XM` extended capability,
+// which is used to enable mouse events.
+fn has_mouse(info: &Database) -> bool {
+    is_xterm(info.name()) ||
+        info.get::<cap::KeyMouse>().is_some() ||
+        info.raw("XM").is_some()
+}
+
+fn color_depth(info: &Database) -> ColorDepth {
+    let colorterm = env::var("COLORTERM").unwrap_or_default();
+
+    // Direct color support is not described by standard terminfo capabilities.
+    // Instead, check the `COLORTERM` environment variable and the
+    // extended capabilities `RGB` and `Tc`.
+    if colorterm == "truecolor" || colorterm == "24bit" ||
+            info.raw("RGB").is_some() || info.raw("Tc").is_some() {
+        return ColorDepth::TrueColor;
+    }
+
+    match info.get::<cap::MaxColors>().map_or(0, |n| n.into()) {
+        n if n >= 256 => ColorDepth::Color256,
+        n if n >= 16 => ColorDepth::Color16,
+        _ => ColorDepth::Color8,
+    }
+}
+
 pub struct PrepareState {
     old_tio: termios,
     old_sigcont: Option<SigAction>,
@@ -1123,6 +2331,9 @@ pub struct PrepareState {
     old_sigwinch: Option<SigAction>,
     restore_keypad: bool,
     restore_mouse: bool,
+    restore_paste: bool,
+    restore_focus: bool,
+    restore_kitty_keyboard: bool,
     prev_resume: Option<Resume>,
 }
 
@@ -1146,6 +2357,7 @@ fn open_rw<P: AsRef<Path>>(path: P) -> io::Result<RawFd> {
     Ok(file.into_raw_fd())
 }
 
+#[derive(Copy, Clone)]
 #[repr(C)]
 struct Winsize {
     ws_row: c_ushort,
@@ -1154,7 +2366,7 @@ struct Winsize {
     ws_ypixel: c_ushort,
 }
 
-fn get_winsize(fd: c_int) -> io::Result<Size> {
+fn winsize(fd: c_int) -> io::Result<Winsize> {
     let mut winsz: Winsize = unsafe { zeroed() };
 
     // `TIOCGWINSZ.into()` is a workaround to a bug in the libc crate:
@@ -1164,15 +2376,38 @@ fn get_winsize(fd: c_int) -> io::Result<Size> {
     if res == -1 {
         Err(io::Error::last_os_error())
     } else {
-        let size = Size{
-            lines: winsz.ws_row as usize,
-            columns: winsz.ws_col as usize,
-        };
-
-        Ok(size)
+        Ok(winsz)
     }
 }
 
+fn get_winsize(fd: c_int) -> io::Result<Size> {
+    let winsz = winsize(fd)?;
+
+    Ok(Size{
+        lines: winsz.ws_row as usize,
+        columns: winsz.ws_col as usize,
+    })
+}
+
+// In SGR-Pixels mode, a mouse event reports the pixel position
+// in place of the cell position. Cell position is then derived
+// from the size of the terminal in pixels.
+fn pixels_to_cell(mouse: &mut MouseEvent, ws: &Winsize) {
+    let pos = PixelPosition{
+        x: mouse.position.column,
+        y: mouse.position.line,
+    };
+
+    let cell_width = (ws.ws_xpixel / ws.ws_col.max(1)).max(1) as usize;
+    let cell_height = (ws.ws_ypixel / ws.ws_row.max(1)).max(1) as usize;
+
+    mouse.position = Cursor{
+        line: (pos.y / cell_height).min((ws.ws_row as usize).saturating_sub(1)),
+        column: (pos.x / cell_width).min((ws.ws_col as usize).saturating_sub(1)),
+    };
+    mouse.pixel_position = Some(pos);
+}
+
 fn nix_to_io(e: nix::Error) -> io::Error {
     io::Error::from_raw_os_error(e as i32)
 }
@@ -1202,7 +2437,9 @@ fn to_timeval(d: Duration) -> TimeVal {
     TimeVal::milliseconds(secs * 1_000 + millis)
 }
 
-fn peek_event(buf: &[u8], sequences: &SeqMap)
+// If `pixels` is given, SGR mouse reports are taken to be in SGR-Pixels mode
+// and positions are converted to cells using the given window size.
+fn peek_event(buf: &[u8], sequences: &SeqMap, pixels: Option<&Winsize>)
         -> io::Result<Option<(Event, usize)>> {
     let (res, n) = {
         let s = utf8_prefix(buf)?;
@@ -1227,31 +2464,342 @@ fn peek_event(buf: &[u8], sequences: &SeqMap)
             }
         }
 
-        let res = last_match.and_then(|(seq, value)| {
-            match value {
-                SeqData::Key(key) => Some((Event::Key(key), seq.len())),
-                SeqData::XTermMouse => {
-                    if let Some((data, len)) = parse_mouse_data(&buf[seq.len()..]) {
-                        Some((Event::Mouse(data), seq.len() + len))
-                    } else {
-                        // Input sequence was incomplete
-                        None
+        let res = match last_match {
+            Some((seq, SeqData::Key(ev))) => Some((ev.into_event(), seq.len())),
+            Some((seq, SeqData::FocusGained)) => Some((Event::FocusGained, seq.len())),
+            Some((seq, SeqData::FocusLost)) => Some((Event::FocusLost, seq.len())),
+            Some((seq, SeqData::XTermMouse)) => {
+                if let Some((mut data, len)) = parse_mouse_data(&buf[seq.len()..]) {
+                    if let Some(ws) = pixels {
+                        pixels_to_cell(&mut data, ws);
                     }
+                    Some((Event::Mouse(data), seq.len() + len))
+                } else {
+                    // Input sequence was incomplete
+                    None
+                }
+            }
+            Some((seq, SeqData::X10Mouse)) => {
+                if let Some((data, len)) = parse_x10_mouse_data(&buf[seq.len()..]) {
+                    Some((Event::Mouse(data), seq.len() + len))
+                } else {
+                    // Input sequence was incomplete
+                    None
+                }
+            }
+            Some((seq, SeqData::XTermPaste)) => {
+                if let Some((text, len)) = parse_paste_data(&buf[seq.len()..]) {
+                    Some((Event::Paste(text), seq.len() + len))
+                } else {
+                    // Wait for the remainder of pasted text
+                    return Ok(None);
                 }
             }
-        });
+            None => None
+        };
 
         if let Some(res) = res {
             res
+        } else if let Some((data, len)) = s.strip_prefix(XTERM_CSI)
+                .filter(|_| sequences.get(XTERM_MOUSE_INTRO).is_some())
+                .and_then(|rest| parse_urxvt_mouse_data(rest.as_bytes())) {
+            (Event::Mouse(data), XTERM_CSI.len() + len)
+        } else if let Some((ev, len)) = s.strip_prefix(XTERM_CSI)
+                .and_then(|rest| parse_xterm_key(rest.as_bytes())) {
+            (ev, XTERM_CSI.len() + len)
+        } else if is_partial_csi(s) && s.len() > XTERM_CSI.len() {
+            // Wait for the final byte of a control sequence
+            return Ok(None);
         } else {
-            let ch = s.chars().next().unwrap();
-            (Event::Key(ch.into()), ch.len_utf8())
+            let mut chars = s.chars();
+            let ch = chars.next().unwrap();
+
+            match chars.next() {
+                // A character sent with the Alt key held is prefixed by ESC.
+                // ESC followed by `[` or `O` and further input is instead
+                // taken to be an unrecognized escape sequence.
+                Some(next) if ch == '\x1b' && next != '\x1b' &&
+                        (!matches!(next, '[' | 'O') || chars.as_str().is_empty()) => {
+                    let ev = KeyEvent::new(next.into(), ModifierState::ALT);
+                    (ev.into_event(), ch.len_utf8() + next.len_utf8())
+                }
+                _ => (Event::Key(ch.into()), ch.len_utf8())
+            }
         }
     };
 
     Ok(Some((res, n)))
 }
 
+// Returns whether the input buffer may contain an incomplete escape sequence,
+// which could be completed by further input.
+fn is_incomplete(buf: &[u8], sequences: &SeqMap) -> bool {
+    let s = match utf8_prefix(buf) {
+        Ok(s) => s,
+        Err(_) => return false
+    };
+
+    if !s.starts_with('\x1b') {
+        return false;
+    }
+
+    // A lone ESC may begin a sequence or an Alt-prefixed character;
+    // ESC followed by `[` or `O` may begin a sequence.
+    if s == "\x1b" || s == "\x1b[" || s == "\x1bO" {
+        return true;
+    }
+
+    match sequences.find(s) {
+        FindResult::Incomplete | FindResult::Undecided(_) => return true,
+        FindResult::NotFound | FindResult::Found(_) => ()
+    }
+
+    // X10 mouse events are followed by three bytes of data
+    if buf.starts_with(X10_MOUSE_INTRO.as_bytes()) &&
+            sequences.get(X10_MOUSE_INTRO).is_some() {
+        return buf.len() < X10_MOUSE_INTRO.len() + 3;
+    }
+
+    is_partial_csi(s)
+}
+
+// Returns whether input is a control sequence consisting only of parameter
+// bytes, which has not yet received its final byte.
+fn is_partial_csi(s: &str) -> bool {
+    match s.strip_prefix(XTERM_CSI) {
+        Some(rest) => rest.bytes().all(|b| (0x30..=0x3f).contains(&b)),
+        None => false
+    }
+}
+
+// Returns pasted text, which is terminated by `XTERM_PASTE_END`,
+// and the length of input consumed.
+//
+// If the terminator is not found within `MAX_PASTE_LEN` bytes, a partial paste
+// is returned and the terminator is not consumed.
+fn parse_paste_data(buf: &[u8]) -> Option<(String, usize)> {
+    let end = XTERM_PASTE_END.as_bytes();
+
+    match buf.windows(end.len()).position(|w| w == end) {
+        Some(pos) if pos <= MAX_PASTE_LEN => {
+            let text = String::from_utf8_lossy(&buf[..pos]).into_owned();
+            Some((text, pos + end.len()))
+        }
+        // Wait until a terminator beginning within the limit would be complete
+        _ if buf.len() >= MAX_PASTE_LEN + end.len() => {
+            // Do not split a UTF-8 sequence between events
+            let mut pos = MAX_PASTE_LEN;
+            while pos > 0 && (buf[pos] & 0xc0) == 0x80 {
+                pos -= 1;
+            }
+
+            let text = String::from_utf8_lossy(&buf[..pos]).into_owned();
+            Some((text, pos))
+        }
+        _ => None
+    }
+}
+
+// Parses an xterm key sequence, which may include a modifier parameter;
+// e.g. `CSI 1 ; 5 C` is Ctrl-Right and `CSI 3 ; 2 ~` is Shift-Delete.
+// Sequences of the kitty keyboard protocol, `CSI code ; modifiers u`,
+// are also parsed. `buf` contains input following `CSI`.
+//
+// Keys which are recognized but not reported yield `Event::NoEvent`.
+fn parse_xterm_key(mut buf: &[u8]) -> Option<(Event, usize)> {
+    let orig_len = buf.len();
+
+    let (n, mut end) = parse_integer(&mut buf)?;
+    let mut shifted = 0;
+    let mut mods_param = 1;
+    let mut kind = KeyEventKind::Press;
+
+    // Alternate key codes: `code : shifted : base`
+    if end == b':' {
+        let (alt, next) = parse_integer(&mut buf)?;
+        shifted = alt;
+        end = next;
+
+        if end == b':' {
+            end = parse_integer(&mut buf)?.1;
+        }
+    }
+
+    // Modifiers and event type: `modifiers : event`
+    if end == b';' {
+        let (param, next) = parse_integer(&mut buf)?;
+        mods_param = param;
+        end = next;
+
+        if end == b':' {
+            let (event, next) = parse_integer(&mut buf)?;
+            kind = match event {
+                2 => KeyEventKind::Repeat,
+                3 => KeyEventKind::Release,
+                _ => KeyEventKind::Press,
+            };
+            end = next;
+        }
+
+        // Associated text: `codepoint : codepoint ...`
+        if end == b';' {
+            loop {
+                end = parse_integer(&mut buf)?.1;
+
+                if end != b':' {
+                    break;
+                }
+            }
+        }
+    }
+
+    let len = orig_len - buf.len();
+
+    if end == b'u' {
+        let ev = match kitty_key(n, shifted, kitty_key_modifiers(mods_param)) {
+            Some((key, modifiers)) => KeyEvent{key, modifiers, kind}.into_event(),
+            None => Event::NoEvent
+        };
+
+        return Some((ev, len));
+    }
+
+    let mut modifiers = xterm_key_modifiers(mods_param);
+
+    let key = match end {
+        b'A' => Key::Up,
+        b'B' => Key::Down,
+        b'C' => Key::Right,
+        b'D' => Key::Left,
+        b'H' => Key::Home,
+        b'F' => Key::End,
+        b'E' => Key::Begin,
+        b'P' ..= b'S' => Key::F((end - b'P') as u32 + 1),
+        b'Z' => {
+            modifiers |= ModifierState::SHIFT;
+            Key::Tab
+        }
+        b'~' => match n {
+            1 | 7 => Key::Home,
+            2 => Key::Insert,
+            3 => Key::Delete,
+            4 | 8 => Key::End,
+            5 => Key::PageUp,
+            6 => Key::PageDown,
+            11 ..= 15 => Key::F(n - 10),
+            17 ..= 21 => Key::F(n - 11),
+            23 | 24 => Key::F(n - 12),
+            _ => return None
+        },
+        _ => return None
+    };
+
+    Some((KeyEvent{key, modifiers, kind}.into_event(), len))
+}
+
+// Converts a kitty keyboard protocol key code into a `Key`.
+// Control and Shift are applied to character keys where possible,
+// consistent with the representation of legacy key input.
+//
+// Returns `None` for keys which are not reported; e.g. modifier keys.
+fn kitty_key(code: u32, shifted: u32, mut mods: ModifierState)
+        -> Option<(Key, ModifierState)> {
+    let key = match code {
+        8 | 127 => Key::Backspace,
+        9 => Key::Tab,
+        13 => Key::Enter,
+        27 => Key::Escape,
+        // Functional keys F13 through F35
+        57376 ..= 57398 => Key::F(code - 57376 + 13),
+        // Keypad keys
+        57399 ..= 57408 => Key::Char(char::from(b'0' + (code - 57399) as u8)),
+        57409 => Key::Char('.'),
+        57410 => Key::Char('/'),
+        57411 => Key::Char('*'),
+        57412 => Key::Char('-'),
+        57413 => Key::Char('+'),
+        57414 => Key::Enter,
+        57415 => Key::Char('='),
+        57416 => Key::Char(','),
+        57417 => Key::Left,
+        57418 => Key::Right,
+        57419 => Key::Up,
+        57420 => Key::Down,
+        57421 => Key::PageUp,
+        57422 => Key::PageDown,
+        57423 => Key::Home,
+        57424 => Key::End,
+        57425 => Key::Insert,
+        57426 => Key::Delete,
+        57427 => Key::Begin,
+        // Other private use codes represent modifier, lock, and media keys
+        57344 ..= 63743 => return None,
+        _ => Key::Char(char::from_u32(code)?),
+    };
+
+    let key = match key {
+        Key::Char(ch) if mods.contains(ModifierState::CTRL) &&
+                ch.is_ascii_alphabetic() => {
+            mods -= ModifierState::CTRL;
+            Key::Ctrl(ch.to_ascii_lowercase())
+        }
+        Key::Char(ch) if mods.contains(ModifierState::SHIFT) => {
+            match char::from_u32(shifted).filter(|_| shifted != 0) {
+                Some(ch) => {
+                    mods -= ModifierState::SHIFT;
+                    Key::Char(ch)
+                }
+                None if ch.is_ascii_lowercase() => {
+                    mods -= ModifierState::SHIFT;
+                    Key::Char(ch.to_ascii_uppercase())
+                }
+                None => key
+            }
+        }
+        _ => key
+    };
+
+    Some((key, mods))
+}
+
+// Converts a kitty keyboard protocol modifier parameter.
+// Unlike xterm, the fourth bit indicates Super rather than Meta.
+fn kitty_key_modifiers(param: u32) -> ModifierState {
+    let bits = param.saturating_sub(1);
+    let mut mods = ModifierState::empty();
+
+    if (bits & XTERM_KEY_SHIFT) != 0 {
+        mods |= ModifierState::SHIFT;
+    }
+    if (bits & (XTERM_KEY_ALT | KITTY_KEY_META)) != 0 {
+        mods |= ModifierState::ALT;
+    }
+    if (bits & XTERM_KEY_CTRL) != 0 {
+        mods |= ModifierState::CTRL;
+    }
+
+    mods
+}
+
+// Converts an xterm key modifier parameter, which is one greater than
+// a bitmask of modifier keys.
+fn xterm_key_modifiers(param: u32) -> ModifierState {
+    let bits = param.saturating_sub(1);
+    let mut mods = ModifierState::empty();
+
+    if (bits & XTERM_KEY_SHIFT) != 0 {
+        mods |= ModifierState::SHIFT;
+    }
+    if (bits & (XTERM_KEY_ALT | XTERM_KEY_META)) != 0 {
+        mods |= ModifierState::ALT;
+    }
+    if (bits & XTERM_KEY_CTRL) != 0 {
+        mods |= ModifierState::CTRL;
+    }
+
+    mods
+}
+
 fn parse_mouse_data(mut buf: &[u8]) -> Option<(MouseEvent, usize)> {
     let orig_len = buf.len();
 
@@ -1275,40 +2823,459 @@ fn parse_mouse_data(mut buf: &[u8]) -> Option<(MouseEvent, usize)> {
         _ => return None
     };
 
-    let mut mods = ModifierState::empty();
-
-    if (input & XTERM_SHIFT_MASK) != 0 {
-        mods |= ModifierState::SHIFT;
-    }
-    if (input & XTERM_META_MASK) != 0 {
-        mods |= ModifierState::ALT;
-    }
-    if (input & XTERM_CTRL_MASK) != 0 {
-        mods |= ModifierState::CTRL;
-    }
+    let mods = mouse_modifiers(input);
 
     input &= !XTERM_MODIFIER_MASK;
 
-    let input = match input {
-        0 ..= 3 => mouse_button_event(input, is_pressed),
-        64 => MouseInput::WheelUp,
-        65 => MouseInput::WheelDown,
-        _ => MouseInput::Motion,
-    };
+    let input = mouse_input(input, is_pressed);
 
     let position = Cursor{
         // Parsed line and column begin at 1; we begin at 0
-        line: (line - 1) as usize,
-        column: (column - 1) as usize,
+        line: line.saturating_sub(1) as usize,
+        column: column.saturating_sub(1) as usize,
     };
 
     Some((MouseEvent{
         position,
         input,
         modifiers: mods,
+        click_count: 0,
+        pixel_position: None,
     }, orig_len - buf.len()))
 }
 
+// Parses X10 encoding, `ESC [ M` followed by three bytes,
+// each a value offset by 32.
+fn parse_x10_mouse_data(buf: &[u8]) -> Option<(MouseEvent, usize)> {
+    match *buf {
+        [input, column, line, ..] => {
+            let input = (input as u32).checked_sub(X10_MOUSE_OFFSET)?;
+            let column = (column as u32).checked_sub(X10_MOUSE_OFFSET)?;
+            let line = (line as u32).checked_sub(X10_MOUSE_OFFSET)?;
+
+            Some((legacy_mouse_event(input, column, line)?, 3))
+        }
+        _ => None
+    }
+}
+
+// Parses urxvt encoding, `ESC [ Cb ; Cx ; Cy M`, where `Cb` is offset by 32.
+fn parse_urxvt_mouse_data(mut buf: &[u8]) -> Option<(MouseEvent, usize)> {
+    let orig_len = buf.len();
+
+    let (input, end) = parse_integer(&mut buf)?;
+
+    if end != b';' {
+        return None;
+    }
+
+    let (column, end) = parse_integer(&mut buf)?;
+
+    if end != b';' {
+        return None;
+    }
+
+    let (line, end) = parse_integer(&mut buf)?;
+
+    if end != b'M' {
+        return None;
+    }
+
+    let input = input.checked_sub(X10_MOUSE_OFFSET)?;
+
+    Some((legacy_mouse_event(input, column, line)?, orig_len - buf.len()))
+}
+
+fn legacy_mouse_event(mut input: u32, column: u32, line: u32) -> Option<MouseEvent> {
+    if column == 0 || line == 0 {
+        return None;
+    }
+
+    let mods = mouse_modifiers(input);
+
+    input &= !XTERM_MODIFIER_MASK;
+
+    // A release is reported as `MouseButton::Other(X10_MOUSE_RELEASE)`;
+    // the released button is filled in by the reader.
+    let input = mouse_input(input, input != X10_MOUSE_RELEASE);
+
+    Some(MouseEvent{
+        position: Cursor{
+            line: (line - 1) as usize,
+            column: (column - 1) as usize,
+        },
+        input,
+        modifiers: mods,
+        click_count: 0,
+        pixel_position: None,
+    })
+}
+
+fn mouse_modifiers(input: u32) -> ModifierState {
+    let mut mods = ModifierState::empty();
+
+    if (input & XTERM_SHIFT_MASK) != 0 {
+        mods |= ModifierState::SHIFT;
+    }
+    if (input & XTERM_META_MASK) != 0 {
+        mods |= ModifierState::ALT;
+    }
+    if (input & XTERM_CTRL_MASK) != 0 {
+        mods |= ModifierState::CTRL;
+    }
+
+    mods
+}
+
+// Returns the position of input at `pos` once `range` has been removed
+fn removed_offset(pos: usize, range: &Range<usize>) -> usize {
+    if pos >= range.end {
+        pos - (range.end - range.start)
+    } else {
+        pos.min(range.start)
+    }
+}
+
+// Removes the first response recognized by `parse` which appears in
+// `buf` at or after `start`, leaving any other input in place.
+fn take_response<T, F>(buf: &mut Vec<u8>, start: usize, parse: F) -> Option<T>
+        where F: Fn(&[u8]) -> Option<(T, usize)> {
+    let (value, pos, len) = buf[start..].iter().enumerate()
+        .filter(|&(_, &b)| b == b'\x1b')
+        .find_map(|(pos, _)| parse(&buf[start + pos..])
+            .map(|(value, len)| (value, start + pos, len)))?;
+
+    let _ = buf.drain(pos..pos + len);
+    Some(value)
+}
+
+// Finds a primary device attributes reply, `CSI ? attrs c`,
+// returning its position and length.
+fn find_device_attrs(buf: &[u8]) -> Option<(usize, usize)> {
+    const INTRO: &[u8] = b"\x1b[?";
+
+    buf.windows(INTRO.len())
+        .enumerate()
+        .filter(|&(_, w)| w == INTRO)
+        .find_map(|(pos, _)| {
+            let rest = &buf[pos + INTRO.len()..];
+            let end = rest.iter().position(|&b| !(b.is_ascii_digit() || b == b';'))?;
+
+            if rest[end] == b'c' {
+                Some((pos, INTRO.len() + end + 1))
+            } else {
+                None
+            }
+        })
+}
+
+// Parses a cursor position report, `CSI line ; column R`
+fn parse_cursor_report(buf: &[u8]) -> Option<(Cursor, usize)> {
+    let mut rest = buf.strip_prefix(XTERM_CSI.as_bytes())?;
+
+    let (line, end) = parse_integer(&mut rest)?;
+
+    if end != b';' {
+        return None;
+    }
+
+    let (column, end) = parse_integer(&mut rest)?;
+
+    if end != b'R' || line == 0 || column == 0 {
+        return None;
+    }
+
+    Some((Cursor{
+        // Reported line and column begin at 1; we begin at 0
+        line: (line - 1) as usize,
+        column: (column - 1) as usize,
+    }, buf.len() - rest.len()))
+}
+
+fn query_request(query: &Query) -> String {
+    match *query {
+        Query::PrimaryDeviceAttributes => "\x1b[c".to_owned(),
+        Query::SecondaryDeviceAttributes => "\x1b[>c".to_owned(),
+        Query::Version => "\x1b[>q".to_owned(),
+        Query::Capability(ref name) =>
+            format!("{}+q{}{}", DCS, hex_encode(name.as_bytes()), ST),
+        Query::Mode(mode) => format!("{}?{}$p", XTERM_CSI, mode),
+        Query::ForegroundColor => format!("{}10;?{}", OSC, ST),
+        Query::BackgroundColor => format!("{}11;?{}", OSC, ST),
+        Query::Clipboard(selection) =>
+            format!("{}52;{};?{}", OSC, selection_name(selection), ST),
+    }
+}
+
+// Parses the reply to the given query, returning the length of input consumed
+fn parse_reply(query: &Query, buf: &[u8]) -> Option<(Reply, usize)> {
+    match *query {
+        Query::PrimaryDeviceAttributes =>
+            parse_csi_params(buf, b"?", b"c")
+                .map(|(params, n)| (Reply::PrimaryDeviceAttributes(params), n)),
+        Query::SecondaryDeviceAttributes =>
+            parse_csi_params(buf, b">", b"c")
+                .map(|(params, n)| (Reply::SecondaryDeviceAttributes(params), n)),
+        Query::Version =>
+            parse_control_string(buf, DCS, b">|")
+                .map(|(s, n)| (Reply::Version(String::from_utf8_lossy(s).into_owned()), n)),
+        Query::Capability(ref name) => parse_capability_reply(buf, name),
+        Query::Mode(mode) => {
+            let (params, n) = parse_csi_params(buf, b"?", b"$y")?;
+
+            match params[..] {
+                [m, status] if m == mode =>
+                    Some((Reply::Mode(mode, mode_status(status)?), n)),
+                _ => None
+            }
+        }
+        Query::ForegroundColor =>
+            parse_control_string(buf, OSC, b"10;")
+                .and_then(|(s, n)| Some((Reply::ForegroundColor(parse_rgb(s)?), n))),
+        Query::BackgroundColor =>
+            parse_control_string(buf, OSC, b"11;")
+                .and_then(|(s, n)| Some((Reply::BackgroundColor(parse_rgb(s)?), n))),
+        Query::Clipboard(selection) => parse_clipboard_reply(buf, selection),
+    }
+}
+
+// Parses a control sequence `CSI intro params final`,
+// where `params` is a series of integers separated by `;`
+fn parse_csi_params(buf: &[u8], intro: &[u8], fin: &[u8]) -> Option<(Vec<u32>, usize)> {
+    let mut rest = buf.strip_prefix(XTERM_CSI.as_bytes())?
+        .strip_prefix(intro)?;
+    let mut params = Vec::new();
+
+    loop {
+        let (n, end) = parse_integer(&mut rest)?;
+
+        params.push(n);
+
+        if end != b';' {
+            if end != fin[0] {
+                return None;
+            }
+            rest = rest.strip_prefix(&fin[1..])?;
+            break;
+        }
+    }
+
+    Some((params, buf.len() - rest.len()))
+}
+
+// Parses a control string, `intro prefix data ST`, returning `data`.
+// Control strings may also be terminated by BEL.
+fn parse_control_string<'a>(buf: &'a [u8], intro: &str, prefix: &[u8])
+        -> Option<(&'a [u8], usize)> {
+    let data = buf.strip_prefix(intro.as_bytes())?
+        .strip_prefix(prefix)?;
+
+    for (i, &b) in data.iter().enumerate() {
+        let term_len = match b {
+            b'\x1b' if data[i + 1..].starts_with(b"\\") => ST.len(),
+            b'\x07' => BEL.len(),
+            // Some other sequence interrupted the control string
+            b'\x1b' if i + 1 < data.len() => return None,
+            _ => continue
+        };
+
+        let len = buf.len() - data.len() + i + term_len;
+        return Some((&data[..i], len));
+    }
+
+    None
+}
+
+// Parses an XTGETTCAP reply, `DCS 1 + r name = value ST`,
+// where `name` and `value` are hex-encoded, or `DCS 0 + r name ST`
+// if the capability is not recognized.
+fn parse_capability_reply(buf: &[u8], name: &str) -> Option<(Reply, usize)> {
+    if let Some((data, n)) = parse_control_string(buf, DCS, b"1+r") {
+        let eq = data.iter().position(|&b| b == b'=')?;
+        let (key, value) = (&data[..eq], &data[eq + 1..]);
+
+        if hex_decode(key)? == name.as_bytes() {
+            let value = String::from_utf8_lossy(&hex_decode(value)?).into_owned();
+            return Some((Reply::Capability(name.to_owned(), Some(value)), n));
+        }
+    } else if let Some((data, n)) = parse_control_string(buf, DCS, b"0+r") {
+        // Some terminals omit the name in a negative reply
+        if data.is_empty() || hex_decode(data)? == name.as_bytes() {
+            return Some((Reply::Capability(name.to_owned(), None), n));
+        }
+    }
+
+    None
+}
+
+fn mode_status(status: u32) -> Option<ModeStatus> {
+    match status {
+        0 => Some(ModeStatus::NotRecognized),
+        1 => Some(ModeStatus::Set),
+        2 => Some(ModeStatus::Reset),
+        3 => Some(ModeStatus::PermanentlySet),
+        4 => Some(ModeStatus::PermanentlyReset),
+        _ => None
+    }
+}
+
+// Parses an X11 color specification, `rgb:R/G/B`, where each component
+// consists of one to four hexadecimal digits.
+fn parse_rgb(spec: &[u8]) -> Option<Color> {
+    let spec = from_utf8(spec).ok()?;
+    let mut parts = spec.strip_prefix("rgb:")?.split('/');
+
+    let mut component = || -> Option<u8> {
+        let part = parts.next()?;
+
+        if part.is_empty() || part.len() > 4 {
+            return None;
+        }
+
+        let value = u32::from_str_radix(part, 16).ok()?;
+        let max = (1u32 << (part.len() * 4)) - 1;
+
+        Some((value * 0xff / max) as u8)
+    };
+
+    let color = Color::Rgb(component()?, component()?, component()?);
+
+    if parts.next().is_some() {
+        return None;
+    }
+
+    Some(color)
+}
+
+// Parses the background color from the value of `COLORFGBG`,
+// which contains palette indices, e.g. `15;0` or `15;default;0`
+fn colorfgbg_background(s: &str) -> Option<Color> {
+    match s.rsplit(';').next()?.parse::<u8>() {
+        Ok(n) if n < 16 => {
+            let (r, g, b) = Color::Indexed(n).to_rgb();
+            Some(Color::Rgb(r, g, b))
+        }
+        _ => None
+    }
+}
+
+// Parses an OSC 52 reply, `OSC 52 ; selection ; data ST`,
+// where `data` is base64-encoded
+fn parse_clipboard_reply(buf: &[u8], selection: Selection) -> Option<(Reply, usize)> {
+    let (data, n) = parse_control_string(buf, OSC, b"52;")?;
+    let data = data.strip_prefix(selection_name(selection).as_bytes())?
+        .strip_prefix(b";")?;
+
+    let text = base64_decode(data)?;
+
+    Some((Reply::Clipboard(selection, String::from_utf8_lossy(&text).into_owned()), n))
+}
+
+fn selection_name(selection: Selection) -> &'static str {
+    match selection {
+        Selection::Clipboard => "c",
+        Selection::Primary => "p",
+        Selection::Secondary => "q",
+    }
+}
+
+fn base64_encode(data: &[u8]) -> String {
+    let mut s = String::with_capacity(data.len() / 3 * 4 + 4);
+
+    for chunk in data.chunks(3) {
+        let n = chunk.iter().enumerate()
+            .fold(0u32, |n, (i, &b)| n | (b as u32) << (16 - i * 8));
+
+        for i in 0..4 {
+            if i <= chunk.len() {
+                s.push(BASE64_CHARS[(n >> (18 - i * 6)) as usize & 0x3f] as char);
+            } else {
+                s.push('=');
+            }
+        }
+    }
+
+    s
+}
+
+fn base64_decode(s: &[u8]) -> Option<Vec<u8>> {
+    let s = match s.iter().position(|&b| b == b'=') {
+        Some(pos) => &s[..pos],
+        None => s
+    };
+
+    let mut data = Vec::with_capacity(s.len() * 3 / 4);
+
+    for chunk in s.chunks(4) {
+        if chunk.len() == 1 {
+            return None;
+        }
+
+        let mut n = 0;
+
+        for (i, &b) in chunk.iter().enumerate() {
+            let value = BASE64_CHARS.iter().position(|&c| c == b)? as u32;
+            n |= value << (18 - i * 6);
+        }
+
+        for i in 0..chunk.len() - 1 {
+            data.push((n >> (16 - i * 8)) as u8);
+        }
+    }
+
+    Some(data)
+}
+
+fn hex_encode(data: &[u8]) -> String {
+    use std::fmt::Write;
+
+    let mut s = String::with_capacity(data.len() * 2);
+
+    for b in data {
+        let _ = write!(s, "{:02X}", b);
+    }
+
+    s
+}
+
+fn hex_decode(s: &[u8]) -> Option<Vec<u8>> {
+    if s.len() % 2 == 1 {
+        return None;
+    }
+
+    s.chunks(2)
+        .map(|pair| from_utf8(pair).ok()
+            .and_then(|pair| u8::from_str_radix(pair, 16).ok()))
+        .collect()
+}
+
+// Parses a window size report, `CSI kind ; height ; width t`
+fn parse_size_report(buf: &[u8], kind: u32) -> Option<(PixelSize, usize)> {
+    let mut rest = buf.strip_prefix(XTERM_CSI.as_bytes())?;
+
+    match parse_integer(&mut rest)? {
+        (n, b';') if n == kind => (),
+        _ => return None
+    }
+
+    let (height, end) = parse_integer(&mut rest)?;
+
+    if end != b';' {
+        return None;
+    }
+
+    let (width, end) = parse_integer(&mut rest)?;
+
+    if end != b't' {
+        return None;
+    }
+
+    Some((PixelSize{
+        width: width as usize,
+        height: height as usize,
+    }, buf.len() - rest.len()))
+}
+
 fn parse_integer(buf: &mut &[u8]) -> Option<(u32, u8)> {
     let mut n = 0u32;
     let mut iter = buf.iter();
@@ -1329,13 +3296,31 @@ fn parse_integer(buf: &mut &[u8]) -> Option<(u32, u8)> {
     None
 }
 
-fn mouse_button_event(input: u32, is_pressed: bool) -> MouseInput {
-    let button = match input {
+fn mouse_input(input: u32, is_pressed: bool) -> MouseInput {
+    let is_motion = (input & XTERM_MOTION_MASK) != 0;
+
+    match input & !XTERM_MOTION_MASK {
+        n @ 0 ..= 2 if is_motion => MouseInput::Drag(mouse_button(n)),
+        n @ 0 ..= 3 if !is_motion => mouse_button_event(n, is_pressed),
+        64 => MouseInput::WheelUp,
+        65 => MouseInput::WheelDown,
+        66 => MouseInput::WheelLeft,
+        67 => MouseInput::WheelRight,
+        _ => MouseInput::Motion,
+    }
+}
+
+fn mouse_button(input: u32) -> MouseButton {
+    match input {
         0 => MouseButton::Left,
         1 => MouseButton::Middle,
         2 => MouseButton::Right,
         _ => MouseButton::Other(input)
-    };
+    }
+}
+
+fn mouse_button_event(input: u32, is_pressed: bool) -> MouseInput {
+    let button = mouse_button(input);
 
     if is_pressed {
         MouseInput::ButtonPressed(button)
@@ -1406,6 +3391,17 @@ fn color_code(color: Color) -> u8 {
         Color::Magenta =>   5,
         Color::Cyan =>      6,
         Color::White =>     7,
+        Color::BrightBlack =>   8,
+        Color::BrightRed =>     9,
+        Color::BrightGreen =>   10,
+        Color::BrightYellow =>  11,
+        Color::BrightBlue =>    12,
+        Color::BrightMagenta => 13,
+        Color::BrightCyan =>    14,
+        Color::BrightWhite =>   15,
+        Color::Indexed(n) => n,
+        // Direct colors are written using `write_rgb`
+        Color::Rgb(..) => unreachable!("rgb color has no palette index"),
     }
 }
 
@@ -1427,3 +3423,660 @@ fn to_u32(u: usize) -> u32 {
 fn to_u32(u: usize) -> u32 {
     u as u32
 }
+
+#[cfg(test)]
+pub mod test {
+    use std::ffi::CStr;
+    use std::os::unix::io::RawFd;
+    use std::time::{Duration, Instant};
+
+    use libc::{c_char, c_int};
+
+    use crate::terminal::{
+        Color, Cursor, Event, Key, KeyEvent, KeyEventKind, ModifierState,
+        MouseButton, MouseEvent, MouseInput, PixelPosition, PixelSize,
+        ModeStatus, PrepareConfig, Query, Reply, Selection,
+    };
+    use super::{
+        colorfgbg_background, is_incomplete, parse_cursor_report, parse_reply, parse_size_report,
+        peek_event, pixels_to_cell, query_request, take_response,
+        base64_decode, base64_encode,
+        find_device_attrs, open_rw, parse_paste_data, MAX_PASTE_LEN,
+        SeqData, SeqMap, Terminal, Winsize,
+    };
+
+    // Opens a pseudo-terminal, returning the master fd and the slave path
+    pub fn open_pty() -> (RawFd, String) {
+        unsafe {
+            let fd = libc::posix_openpt(libc::O_RDWR | libc::O_NOCTTY);
+            assert!(fd >= 0, "posix_openpt failed");
+            assert_eq!(libc::grantpt(fd), 0);
+            assert_eq!(libc::unlockpt(fd), 0);
+
+            let ws = libc::winsize{ws_row: 24, ws_col: 80, ws_xpixel: 0, ws_ypixel: 0};
+            assert_eq!(libc::ioctl(fd, libc::TIOCSWINSZ, &ws), 0);
+
+            let mut buf = [0 as c_char; 128];
+            assert_eq!(libc::ptsname_r(fd, buf.as_mut_ptr(), buf.len()), 0);
+
+            let flags = libc::fcntl(fd, libc::F_GETFL);
+            libc::fcntl(fd, libc::F_SETFL, flags | libc::O_NONBLOCK);
+
+            (fd, CStr::from_ptr(buf.as_ptr()).to_string_lossy().into_owned())
+        }
+    }
+
+    // Opens a pseudo-terminal and a `Terminal` on its slave device,
+    // returning the master fd. The `xterm-256color` entry is required,
+    // so that tests do not depend upon the environment.
+    pub fn open_pty_terminal() -> (RawFd, Terminal) {
+        let (master, path) = open_pty();
+
+        let term = Terminal::open_with_name(&path, "xterm-256color")
+            .unwrap_or_else(|e| panic!(
+                "failed to open {} with terminfo entry xterm-256color: {}", path, e));
+
+        (master, term)
+    }
+
+    // Reads all output currently available from the master fd
+    pub fn read_pty(fd: RawFd) -> String {
+        let mut out = Vec::new();
+        let mut buf = [0; 1024];
+
+        loop {
+            let n = unsafe { libc::read(fd, buf.as_mut_ptr() as *mut _, buf.len()) };
+
+            if n <= 0 {
+                break;
+            }
+
+            out.extend_from_slice(&buf[..n as usize]);
+        }
+
+        String::from_utf8_lossy(&out).into_owned()
+    }
+
+    fn write_pty(fd: RawFd, data: &[u8]) {
+        let n = unsafe { libc::write(fd, data.as_ptr() as *const _, data.len()) };
+        assert_eq!(n, data.len() as isize);
+    }
+
+    fn key_mod(key: Key, modifiers: ModifierState) -> Event {
+        Event::KeyMod(KeyEvent::new(key, modifiers))
+    }
+
+    fn peek(seqs: &SeqMap, s: &str) -> Option<(Event, usize)> {
+        peek_event(s.as_bytes(), seqs, None).unwrap()
+    }
+
+    #[test]
+    fn test_peek_key_modifiers() {
+        let seqs = SeqMap::new();
+
+        assert_eq!(peek(&seqs, "\x1b[1;5C"),
+            Some((key_mod(Key::Right, ModifierState::CTRL), 6)));
+        assert_eq!(peek(&seqs, "\x1b[1;6Dx"),
+            Some((key_mod(Key::Left, ModifierState::CTRL | ModifierState::SHIFT), 6)));
+        assert_eq!(peek(&seqs, "\x1b[3;3~"),
+            Some((key_mod(Key::Delete, ModifierState::ALT), 6)));
+        assert_eq!(peek(&seqs, "\x1b[15;2~"),
+            Some((key_mod(Key::F(5), ModifierState::SHIFT), 7)));
+        assert_eq!(peek(&seqs, "\x1b[1;9P"),
+            Some((key_mod(Key::F(1), ModifierState::ALT), 6)));
+        assert_eq!(peek(&seqs, "\x1b[Z"),
+            Some((key_mod(Key::Tab, ModifierState::SHIFT), 3)));
+        assert_eq!(peek(&seqs, "\x1b[1;5E"),
+            Some((key_mod(Key::Begin, ModifierState::CTRL), 6)));
+        assert_eq!(peek(&seqs, "\x1b[A"), Some((Event::Key(Key::Up), 3)));
+    }
+
+    #[test]
+    fn test_partial_sequence_timeout() {
+        let (master, term) = open_pty_terminal();
+
+        let timeout = Some(Duration::from_millis(20));
+
+        // Without an escape timeout, the remainder of a sequence is awaited
+        let state = term.prepare(Default::default()).unwrap();
+
+        write_pty(master, b"\x1b[1;5");
+        assert_eq!(term.read_event(timeout).unwrap(), None);
+        write_pty(master, b"C");
+        assert_eq!(term.read_event(timeout).unwrap(),
+            Some(Event::KeyMod(KeyEvent::new(Key::Right, ModifierState::CTRL))));
+
+        term.restore(state).unwrap();
+
+        // Otherwise, an incomplete sequence begins with the Escape key
+        let state = term.prepare(PrepareConfig{
+            escape_timeout: timeout,
+            .. PrepareConfig::default()
+        }).unwrap();
+
+        write_pty(master, b"\x1b[1;5");
+        assert_eq!(term.read_event(None).unwrap(), Some(Event::Key(Key::Escape)));
+
+        term.restore(state).unwrap();
+        unsafe { libc::close(master as c_int); }
+    }
+
+    #[test]
+    fn test_escape_timeout_deadline() {
+        let (master, term) = open_pty_terminal();
+
+        let escape_timeout = Duration::from_millis(200);
+
+        let state = term.prepare(PrepareConfig{
+            escape_timeout: Some(escape_timeout),
+            .. PrepareConfig::default()
+        }).unwrap();
+
+        // A read with a shorter timeout returns when its own timeout expires
+        let start = Instant::now();
+        write_pty(master, b"\x1b[1;5");
+        assert_eq!(term.read_event(Some(Duration::from_millis(150))).unwrap(), None);
+        assert!(start.elapsed() < escape_timeout);
+
+        // The next read completes the wait, rather than beginning it again
+        assert_eq!(term.read_event(None).unwrap(), Some(Event::Key(Key::Escape)));
+        assert!(start.elapsed() < Duration::from_millis(300));
+
+        term.restore(state).unwrap();
+        unsafe { libc::close(master as c_int); }
+    }
+
+    #[test]
+    fn test_peek_kitty_keys() {
+        let seqs = SeqMap::new();
+
+        fn key_kind(key: Key, modifiers: ModifierState, kind: KeyEventKind) -> Event {
+            Event::KeyMod(KeyEvent{key, modifiers, kind})
+        }
+
+        assert_eq!(peek(&seqs, "\x1b[27u"), Some((Event::Key(Key::Escape), 5)));
+        assert_eq!(peek(&seqs, "\x1b[105;5u"), Some((Event::Key(Key::Ctrl('i')), 8)));
+        assert_eq!(peek(&seqs, "\x1b[9;5u"),
+            Some((key_mod(Key::Tab, ModifierState::CTRL), 6)));
+        assert_eq!(peek(&seqs, "\x1b[97;3u"),
+            Some((key_mod(Key::Char('a'), ModifierState::ALT), 7)));
+        assert_eq!(peek(&seqs, "\x1b[97;4u"),
+            Some((key_mod(Key::Char('A'), ModifierState::ALT), 7)));
+        assert_eq!(peek(&seqs, "\x1b[49:33;4u"),
+            Some((key_mod(Key::Char('!'), ModifierState::ALT), 10)));
+        assert_eq!(peek(&seqs, "\x1b[97;6u"),
+            Some((key_mod(Key::Ctrl('a'), ModifierState::SHIFT), 7)));
+        assert_eq!(peek(&seqs, "\x1b[97;1:2u"),
+            Some((key_kind(Key::Char('a'), ModifierState::empty(), KeyEventKind::Repeat), 9)));
+        assert_eq!(peek(&seqs, "\x1b[97;1:3u"),
+            Some((key_kind(Key::Char('a'), ModifierState::empty(), KeyEventKind::Release), 9)));
+        assert_eq!(peek(&seqs, "\x1b[1;5:3A"),
+            Some((key_kind(Key::Up, ModifierState::CTRL, KeyEventKind::Release), 8)));
+        assert_eq!(peek(&seqs, "\x1b[57376u"), Some((Event::Key(Key::F(13)), 8)));
+        assert_eq!(peek(&seqs, "\x1b[57441;2u"), Some((Event::NoEvent, 10)));
+        assert_eq!(peek(&seqs, "\x1b[97;1;97u"), Some((Event::Key(Key::Char('a')), 10)));
+    }
+
+    #[test]
+    fn test_peek_alt_prefix() {
+        let seqs = SeqMap::new();
+
+        assert_eq!(peek(&seqs, "\x1bf"),
+            Some((key_mod(Key::Char('f'), ModifierState::ALT), 2)));
+        assert_eq!(peek(&seqs, "\x1bF"),
+            Some((key_mod(Key::Char('F'), ModifierState::ALT), 2)));
+        assert_eq!(peek(&seqs, "\x1b\x01"),
+            Some((key_mod(Key::Ctrl('a'), ModifierState::ALT), 2)));
+        assert_eq!(peek(&seqs, "\x1b["),
+            Some((key_mod(Key::Char('['), ModifierState::ALT), 2)));
+        assert_eq!(peek(&seqs, "\x1b"), Some((Event::Key(Key::Escape), 1)));
+        assert_eq!(peek(&seqs, "\x1b\x1b"), Some((Event::Key(Key::Escape), 1)));
+        assert_eq!(peek(&seqs, "\x1b[1;5"), None);
+        assert_eq!(peek(&seqs, "\x1b[<0;10"), None);
+    }
+
+    #[test]
+    fn test_incomplete_sequence() {
+        let mut seqs = SeqMap::new();
+
+        seqs.insert("\x1bOa".into(),
+            SeqData::Key(KeyEvent::new(Key::Up, ModifierState::CTRL)));
+
+        assert!(is_incomplete(b"\x1b", &seqs));
+        assert!(is_incomplete(b"\x1b[", &seqs));
+        assert!(is_incomplete(b"\x1bO", &seqs));
+        assert!(is_incomplete(b"\x1b[1;5", &seqs));
+        assert!(is_incomplete(b"\x1b[<0;10", &seqs));
+        assert!(!is_incomplete(b"\x1bOa", &seqs));
+        assert!(!is_incomplete(b"\x1b[1;5C", &seqs));
+        assert!(!is_incomplete(b"\x1bf", &seqs));
+        assert!(!is_incomplete(b"\x1b\x1b", &seqs));
+        assert!(!is_incomplete(b"x\x1b", &seqs));
+    }
+
+    #[test]
+    fn test_peek_mouse() {
+        let mut seqs = SeqMap::new();
+
+        seqs.insert("\x1b[<".into(), SeqData::XTermMouse);
+        seqs.insert("\x1b[M".into(), SeqData::X10Mouse);
+
+        fn mouse(input: MouseInput, line: usize, column: usize,
+                modifiers: ModifierState) -> Event {
+            Event::Mouse(MouseEvent{
+                position: Cursor{line, column},
+                input,
+                modifiers,
+                click_count: 0,
+                pixel_position: None,
+            })
+        }
+
+        let left = MouseInput::ButtonPressed(MouseButton::Left);
+
+        assert_eq!(peek(&seqs, "\x1b[<0;3;2M"),
+            Some((mouse(left, 1, 2, ModifierState::empty()), 9)));
+        assert_eq!(peek_event(b"\x1b[M\x24\x21\xa1", &seqs, None).unwrap(),
+            Some((mouse(left, 128, 0, ModifierState::SHIFT), 6)));
+        assert_eq!(peek(&seqs, "\x1b[M\x23\x22\x21x"),
+            Some((mouse(MouseInput::ButtonReleased(MouseButton::Other(3)),
+                0, 1, ModifierState::empty()), 6)));
+        assert_eq!(peek(&seqs, "\x1b[M\x60!!"),
+            Some((mouse(MouseInput::WheelUp, 0, 0, ModifierState::empty()), 6)));
+        assert_eq!(peek(&seqs, "\x1b[34;10;5M"),
+            Some((mouse(MouseInput::ButtonPressed(MouseButton::Right),
+                4, 9, ModifierState::empty()), 10)));
+        assert_eq!(peek(&seqs, "\x1b[48;1;1M"),
+            Some((mouse(left, 0, 0, ModifierState::CTRL), 9)));
+        assert_eq!(peek(&seqs, "\x1b[<34;5;5M"),
+            Some((mouse(MouseInput::Drag(MouseButton::Right),
+                4, 4, ModifierState::empty()), 10)));
+        assert_eq!(peek(&seqs, "\x1b[<35;5;5M"),
+            Some((mouse(MouseInput::Motion, 4, 4, ModifierState::empty()), 10)));
+        assert_eq!(peek(&seqs, "\x1b[M\x40!!"),
+            Some((mouse(MouseInput::Drag(MouseButton::Left),
+                0, 0, ModifierState::empty()), 6)));
+        assert_eq!(peek(&seqs, "\x1b[<66;1;1M"),
+            Some((mouse(MouseInput::WheelLeft, 0, 0, ModifierState::empty()), 10)));
+        assert_eq!(peek(&seqs, "\x1b[<67;1;1M"),
+            Some((mouse(MouseInput::WheelRight, 0, 0, ModifierState::empty()), 10)));
+
+        assert!(is_incomplete(b"\x1b[M\x20", &seqs));
+        assert!(!is_incomplete(b"\x1b[M\x20!!", &seqs));
+    }
+
+    #[test]
+    fn test_mouse_pixels() {
+        let ws = Winsize{ws_row: 24, ws_col: 80, ws_xpixel: 640, ws_ypixel: 384};
+
+        let mut mouse = MouseEvent{
+            position: Cursor{line: 40, column: 100},
+            input: MouseInput::Motion,
+            modifiers: ModifierState::empty(),
+            click_count: 0,
+            pixel_position: None,
+        };
+
+        pixels_to_cell(&mut mouse, &ws);
+
+        assert_eq!(mouse.position, Cursor{line: 2, column: 12});
+        assert_eq!(mouse.pixel_position, Some(PixelPosition{x: 100, y: 40}));
+
+        mouse.position = Cursor{line: 1000, column: 1000};
+        pixels_to_cell(&mut mouse, &ws);
+
+        assert_eq!(mouse.position, Cursor{line: 23, column: 79});
+
+        let mut seqs = SeqMap::new();
+
+        seqs.insert("\x1b[<".into(), SeqData::XTermMouse);
+        seqs.insert("\x1b[M".into(), SeqData::X10Mouse);
+
+        fn position(ev: Option<(Event, usize)>) -> (Cursor, Option<PixelPosition>) {
+            match ev {
+                Some((Event::Mouse(mouse), _)) => (mouse.position, mouse.pixel_position),
+                ev => panic!("expected mouse event; got {:?}", ev)
+            }
+        }
+
+        // Only SGR reports are in pixels
+        assert_eq!(position(peek_event(b"\x1b[<35;101;41M", &seqs, Some(&ws)).unwrap()),
+            (Cursor{line: 2, column: 12}, Some(PixelPosition{x: 100, y: 40})));
+        assert_eq!(position(peek_event(b"\x1b[M\x43\x25\x23", &seqs, Some(&ws)).unwrap()),
+            (Cursor{line: 2, column: 4}, None));
+        assert_eq!(position(peek_event(b"\x1b[35;5;3M", &seqs, Some(&ws)).unwrap()),
+            (Cursor{line: 2, column: 4}, None));
+    }
+
+    #[test]
+    fn test_device_attrs() {
+        assert_eq!(find_device_attrs(b"\x1b[?62;22c"), Some((0, 9)));
+        assert_eq!(find_device_attrs(b"ab\x1b[3;4R\x1b[?1cd"), Some((8, 5)));
+        assert_eq!(find_device_attrs(b"\x1b[?1049h\x1b[?6c"), Some((8, 5)));
+        assert_eq!(find_device_attrs(b"\x1b[?62;22"), None);
+        assert_eq!(find_device_attrs(b"\x1b[62c"), None);
+    }
+
+    #[test]
+    fn test_request_sentinel() {
+        let (master, term) = open_pty_terminal();
+
+        let state = term.prepare(Default::default()).unwrap();
+        let timeout = Some(Duration::from_millis(50));
+
+        // Response followed by the device attributes reply
+        write_pty(master, b"\x1b[5;6R\x1b[?62c");
+        assert_eq!(term.cursor_position(timeout).unwrap(),
+            Some(Cursor{line: 4, column: 5}));
+
+        // Unsupported request; only device attributes are reported
+        write_pty(master, b"\x1b[?62c");
+        assert_eq!(term.cursor_position(None).unwrap(), None);
+
+        // Late replies are discarded when they arrive
+        assert_eq!(term.cursor_position(timeout).unwrap(), None);
+        write_pty(master, b"\x1b[7;8R\x1b[?62cx");
+        assert_eq!(term.read_event(timeout).unwrap(), Some(Event::Key(Key::Char('x'))));
+
+        let out = read_pty(master);
+        assert_eq!(out.matches("\x1b[6n\x1b[c").count(), 3);
+
+        term.restore(state).unwrap();
+        unsafe { libc::close(master as c_int); }
+    }
+
+    #[test]
+    fn test_sync_output_query() {
+        let (master, term) = open_pty_terminal();
+
+        let state = term.prepare(Default::default()).unwrap();
+
+        // The mode is recognized, though not set
+        write_pty(master, b"\x1b[?2026;2$y\x1b[?62c");
+        assert!(term.has_sync_output().unwrap());
+        assert!(read_pty(master).ends_with("\x1b[?2026$p\x1b[c"));
+
+        // The result is retained
+        assert!(term.has_sync_output().unwrap());
+
+        let mut writer = term.lock_write().unwrap();
+        writer.begin_sync_output().unwrap();
+        writer.end_sync_output().unwrap();
+        writer.flush().unwrap();
+        drop(writer);
+        assert_eq!(read_pty(master), "\x1b[?2026h\x1b[?2026l");
+
+        term.restore(state).unwrap();
+        unsafe { libc::close(master as c_int); }
+    }
+
+    #[test]
+    fn test_late_reply_input() {
+        let (master, term) = open_pty_terminal();
+
+        let state = term.prepare(Default::default()).unwrap();
+        let timeout = Some(Duration::from_millis(50));
+
+        fn key(ch: char) -> Option<Event> {
+            Some(Event::Key(Key::Char(ch)))
+        }
+
+        // Input around a late reply is left in place
+        write_pty(master, b"q");
+        assert_eq!(term.cursor_position(timeout).unwrap(), None);
+        write_pty(master, b"ab\x1b[7;8R\x1b[?62cc");
+
+        assert_eq!(term.read_event(timeout).unwrap(), key('q'));
+        assert_eq!(term.read_event(timeout).unwrap(), key('a'));
+        assert_eq!(term.read_event(timeout).unwrap(), key('b'));
+        assert_eq!(term.read_event(timeout).unwrap(), key('c'));
+        assert_eq!(term.read_event(timeout).unwrap(), None);
+
+        // A later query receives its own response
+        assert_eq!(term.cursor_position(timeout).unwrap(), None);
+        write_pty(master, b"x\x1b[7;8R\x1b[?62cy\x1b[2;3R\x1b[?62cz");
+        assert_eq!(term.cursor_position(timeout).unwrap(),
+            Some(Cursor{line: 1, column: 2}));
+
+        assert_eq!(term.read_event(timeout).unwrap(), key('x'));
+        assert_eq!(term.read_event(timeout).unwrap(), key('y'));
+        assert_eq!(term.read_event(timeout).unwrap(), key('z'));
+
+        term.restore(state).unwrap();
+        unsafe { libc::close(master as c_int); }
+    }
+
+    #[test]
+    fn test_title_restore() {
+        let (master, term) = open_pty_terminal();
+
+        term.set_title("foo").unwrap();
+        term.set_title("bar").unwrap();
+        drop(term);
+
+        let out = read_pty(master);
+
+        assert!(out.starts_with("\x1b[22;0t\x1b]0;foo\x07\x1b]0;bar\x07"));
+        assert!(out.ends_with("\x1b[23;0t"));
+        assert_eq!(out.matches("\x1b[22;0t").count(), 1);
+
+        unsafe { libc::close(master as c_int); }
+    }
+
+    #[test]
+    fn test_link_restore() {
+        let (master, term) = open_pty_terminal();
+
+        let state = term.prepare(Default::default()).unwrap();
+        term.set_link(Some("http://example.com")).unwrap();
+        term.restore(state).unwrap();
+
+        let out = read_pty(master);
+        let open = out.find("\x1b]8;;http://example.com\x07").unwrap();
+        assert!(out[open..].contains("\x1b]8;;\x07"));
+
+        term.set_link(Some("http://example.com")).unwrap();
+        drop(term);
+
+        assert!(read_pty(master).ends_with("\x1b]8;;\x07"));
+
+        unsafe { libc::close(master as c_int); }
+    }
+
+    #[test]
+    fn test_underline_color() {
+        use terminfo::Database;
+        use terminfo::capability::Value;
+
+        fn open(path: &str, setulc: &[u8]) -> Terminal {
+            let mut info = Database::new();
+            info.name("test").description("test")
+                .raw("RGB", Value::True)
+                .raw("Setulc", Value::String(setulc.to_vec()));
+
+            let fd = open_rw(path).unwrap();
+            Terminal::with_info(info.build().unwrap(), fd, fd, true)
+        }
+
+        let (master, path) = open_pty();
+
+        let term = open(&path,
+            b"\x1b[58;2;%p1%{65536}%/%d;%p1%{256}%/%{255}%&%d;%p1%{255}%&%dm");
+
+        term.set_underline_color(Some(Color::Rgb(1, 2, 3))).unwrap();
+        assert_eq!(read_pty(master), "\x1b[58;2;1;2;3m");
+
+        term.set_underline_color(Some(Color::Indexed(100))).unwrap();
+        assert_eq!(read_pty(master), "\x1b[58:5:100m");
+
+        term.set_underline_color(None).unwrap();
+        assert_eq!(read_pty(master), "\x1b[59m");
+
+        drop(term);
+
+        // An entry which fails to expand is replaced by the SGR sequence
+        let term = open(&path,
+            b"\x1b[58:2::%p1%{65536}%/%d:%p1%{256}%/%{255}%&%d:%p1%{255}%&%d%;m");
+
+        term.set_underline_color(Some(Color::Rgb(4, 5, 6))).unwrap();
+        assert_eq!(read_pty(master), "\x1b[58:2::4:5:6m");
+
+        drop(term);
+        unsafe { libc::close(master as c_int); }
+    }
+
+    #[test]
+    fn test_cursor_report() {
+        assert_eq!(parse_cursor_report(b"\x1b[12;40Rx"),
+            Some((Cursor{line: 11, column: 39}, 8)));
+        assert_eq!(parse_cursor_report(b"\x1b[12;40"), None);
+        assert_eq!(parse_cursor_report(b"\x1b[12R"), None);
+        assert_eq!(parse_cursor_report(b"x\x1b[1;1R"), None);
+
+        let mut buf = b"ab\x1b[Acd\x1b[3;4Ref".to_vec();
+
+        assert_eq!(take_response(&mut buf, 1, parse_cursor_report),
+            Some(Cursor{line: 2, column: 3}));
+        assert_eq!(buf, b"ab\x1b[Acdef");
+        assert_eq!(take_response(&mut buf, 0, parse_cursor_report), None);
+    }
+
+    #[test]
+    fn test_query_reply() {
+        fn reply(query: Query, s: &str) -> Option<(Reply, usize)> {
+            parse_reply(&query, s.as_bytes())
+        }
+
+        assert_eq!(reply(Query::PrimaryDeviceAttributes, "\x1b[?62;22c"),
+            Some((Reply::PrimaryDeviceAttributes(vec![62, 22]), 9)));
+        assert_eq!(reply(Query::SecondaryDeviceAttributes, "\x1b[>41;367;0cx"),
+            Some((Reply::SecondaryDeviceAttributes(vec![41, 367, 0]), 12)));
+        assert_eq!(reply(Query::SecondaryDeviceAttributes, "\x1b[?62c"), None);
+        assert_eq!(reply(Query::Version, "\x1bP>|XTerm(367)\x1b\\"),
+            Some((Reply::Version("XTerm(367)".to_owned()), 16)));
+        assert_eq!(reply(Query::Version, "\x1bP>|XTerm(367)"), None);
+        assert_eq!(reply(Query::Capability("TN".to_owned()),
+                "\x1bP1+r544E=787465726D\x1b\\"),
+            Some((Reply::Capability("TN".to_owned(), Some("xterm".to_owned())), 22)));
+        assert_eq!(reply(Query::Capability("Xy".to_owned()), "\x1bP0+r5879\x1b\\"),
+            Some((Reply::Capability("Xy".to_owned(), None), 11)));
+        assert_eq!(reply(Query::Mode(2026), "\x1b[?2026;2$y"),
+            Some((Reply::Mode(2026, ModeStatus::Reset), 11)));
+        assert_eq!(reply(Query::Mode(2004), "\x1b[?2026;2$y"), None);
+        assert_eq!(reply(Query::BackgroundColor, "\x1b]11;rgb:ffff/8080/0000\x07"),
+            Some((Reply::BackgroundColor(Color::Rgb(0xff, 0x80, 0)), 24)));
+        assert_eq!(reply(Query::ForegroundColor, "\x1b]10;rgb:f/8/0\x1b\\"),
+            Some((Reply::ForegroundColor(Color::Rgb(0xff, 0x88, 0)), 16)));
+        assert_eq!(reply(Query::Clipboard(Selection::Clipboard), "\x1b]52;c;Zm9vYmFy\x07"),
+            Some((Reply::Clipboard(Selection::Clipboard, "foobar".to_owned()), 16)));
+        assert_eq!(reply(Query::Clipboard(Selection::Primary), "\x1b]52;p;\x1b\\"),
+            Some((Reply::Clipboard(Selection::Primary, String::new()), 9)));
+        assert_eq!(reply(Query::Clipboard(Selection::Primary), "\x1b]52;c;Zm9v\x07"), None);
+        assert_eq!(reply(Query::ForegroundColor, "\x1b]11;rgb:f/8/0\x1b\\"), None);
+
+        assert_eq!(query_request(&Query::Capability("TN".to_owned())),
+            "\x1bP+q544E\x1b\\");
+        assert_eq!(query_request(&Query::Mode(2026)), "\x1b[?2026$p");
+    }
+
+    #[test]
+    fn test_base64() {
+        assert_eq!(base64_encode(b""), "");
+        assert_eq!(base64_encode(b"f"), "Zg==");
+        assert_eq!(base64_encode(b"fo"), "Zm8=");
+        assert_eq!(base64_encode(b"foo"), "Zm9v");
+        assert_eq!(base64_encode(b"foob"), "Zm9vYg==");
+        assert_eq!(base64_encode("\u{e9}t\u{e9}".as_bytes()), "w6l0w6k=");
+
+        assert_eq!(base64_decode(b"").unwrap(), b"");
+        assert_eq!(base64_decode(b"Zg==").unwrap(), b"f");
+        assert_eq!(base64_decode(b"Zm8").unwrap(), b"fo");
+        assert_eq!(base64_decode(b"Zm9vYmFy").unwrap(), b"foobar");
+        assert_eq!(base64_decode(b"Zm9vYg").unwrap(), b"foob");
+        assert_eq!(base64_decode(b"Zm9vY"), None);
+        assert_eq!(base64_decode(b"Zm9!"), None);
+    }
+
+    #[test]
+    fn test_colorfgbg() {
+        assert_eq!(colorfgbg_background("15;0"), Some(Color::Rgb(0, 0, 0)));
+        assert_eq!(colorfgbg_background("0;default;15"),
+            Some(Color::Rgb(0xff, 0xff, 0xff)));
+        assert_eq!(colorfgbg_background("15;default"), None);
+        assert_eq!(colorfgbg_background("15;16"), None);
+    }
+
+    #[test]
+    fn test_size_report() {
+        assert_eq!(parse_size_report(b"\x1b[4;768;1024tx", 4),
+            Some((PixelSize{width: 1024, height: 768}, 13)));
+        assert_eq!(parse_size_report(b"\x1b[6;16;8t", 6),
+            Some((PixelSize{width: 8, height: 16}, 9)));
+        assert_eq!(parse_size_report(b"\x1b[6;16;8t", 4), None);
+        assert_eq!(parse_size_report(b"\x1b[4;768;10", 4), None);
+    }
+
+    #[test]
+    fn test_peek_paste() {
+        let mut seqs = SeqMap::new();
+
+        seqs.insert("\x1b[200~".into(), SeqData::XTermPaste);
+
+        assert_eq!(peek(&seqs, "\x1b[200~foo\rbar\x1b[201~x"),
+            Some((Event::Paste("foo\rbar".to_owned()), 19)));
+        assert_eq!(peek(&seqs, "\x1b[200~\x1b[201~"),
+            Some((Event::Paste(String::new()), 12)));
+        assert_eq!(peek(&seqs, "\x1b[200~foo\x1b[20"), None);
+    }
+
+    #[test]
+    fn test_paste_limit() {
+        let mut buf = vec![b'a'; MAX_PASTE_LEN + 5];
+
+        assert_eq!(parse_paste_data(&buf), None);
+
+        buf.push(b'b');
+
+        let (text, n) = parse_paste_data(&buf).unwrap();
+        assert_eq!(n, MAX_PASTE_LEN);
+        assert_eq!(text.len(), MAX_PASTE_LEN);
+        assert_eq!(parse_paste_data(&buf[n..]), None);
+
+        // A terminator at the limit ends the paste
+        buf.truncate(MAX_PASTE_LEN);
+        buf.extend_from_slice(b"\x1b[201~");
+        assert_eq!(parse_paste_data(&buf).map(|(_, n)| n), Some(buf.len()));
+
+        // Multibyte characters are not split
+        let mut buf = vec![b'a'; MAX_PASTE_LEN - 1];
+        buf.extend_from_slice("\u{e9}".as_bytes());
+        buf.extend_from_slice(b"bbbbbb");
+
+        let (text, n) = parse_paste_data(&buf).unwrap();
+        assert_eq!(n, MAX_PASTE_LEN - 1);
+        assert!(text.bytes().all(|b| b == b'a'));
+        assert_eq!(parse_paste_data(&buf[n..]), None);
+    }
+
+    #[test]
+    fn test_peek_focus() {
+        let mut seqs = SeqMap::new();
+
+        seqs.insert("\x1b[I".into(), SeqData::FocusGained);
+        seqs.insert("\x1b[O".into(), SeqData::FocusLost);
+
+        assert_eq!(peek(&seqs, "\x1b[I"), Some((Event::FocusGained, 3)));
+        assert_eq!(peek(&seqs, "\x1b[Ox"), Some((Event::FocusLost, 3)));
+    }
+
+    #[test]
+    fn test_peek_terminfo_modifiers() {
+        let mut seqs = SeqMap::new();
+
+        seqs.insert("\x1bOa".into(),
+            SeqData::Key(KeyEvent::new(Key::Up, ModifierState::CTRL)));
+
+        assert_eq!(peek(&seqs, "\x1bOa"),
+            Some((key_mod(Key::Up, ModifierState::CTRL), 3)));
+    }
+}
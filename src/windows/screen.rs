@@ -14,7 +14,10 @@ use crate::sys::terminal::{
     size_event, PrepareState,
     Terminal, TerminalReadGuard, TerminalWriteGuard,
 };
-use crate::terminal::{Color, Cursor, CursorMode, Event, PrepareConfig, Size, Style};
+use crate::terminal::{
+    Color, ColorDepth, Cursor, CursorMode, Event, PixelSize, PrepareConfig,
+    Selection, Size, Theme,
+};
 
 pub struct Screen {
     term: Terminal,
@@ -105,10 +108,34 @@ impl Screen {
         self.term.name()
     }
 
+    pub fn color_depth(&self) -> ColorDepth {
+        self.term.color_depth()
+    }
+
+    pub fn pixel_size(&self) -> io::Result<Option<PixelSize>> {
+        self.term.pixel_size()
+    }
+
+    pub fn cell_size(&self) -> io::Result<Option<PixelSize>> {
+        self.term.cell_size()
+    }
+
+    pub fn background_color(&self) -> io::Result<Option<Color>> {
+        self.term.background_color()
+    }
+
     pub fn set_cursor_mode(&self, mode: CursorMode) -> io::Result<()> {
         self.term.set_cursor_mode(mode)
     }
 
+    pub fn set_title(&self, title: &str) -> io::Result<()> {
+        self.term.set_title(title)
+    }
+
+    pub fn set_clipboard(&self, selection: Selection, text: &str) -> io::Result<()> {
+        self.term.set_clipboard(selection, text)
+    }
+
     pub fn wait_event(&self, timeout: Option<Duration>) -> io::Result<bool> {
         self.lock_reader().wait_event(timeout)
     }
@@ -207,24 +234,44 @@ impl<'a> ScreenWriteGuard<'a> {
         self.writer.set_cursor_mode(mode)
     }
 
+    pub fn set_title(&mut self, title: &str) -> io::Result<()> {
+        self.writer.set_title(title)
+    }
+
+    pub fn set_clipboard(&mut self, selection: Selection, text: &str) -> io::Result<()> {
+        self.writer.set_clipboard(selection, text)
+    }
+
     pub fn refresh(&mut self) -> io::Result<()> {
         if self.data.clear_screen {
             self.writer.clear_screen()?;
             self.data.clear_screen = false;
+        } else {
+            self.scroll_lines()?;
         }
 
-        let mut real_attrs = (None, None, Style::empty());
+        let mut real_attrs = Theme::default();
 
         self.writer.clear_attributes()?;
 
         let mut indices = self.data.buffer.indices();
 
         while let Some((pos, cell)) = self.data.buffer.next_cell(&mut indices) {
+            let run = self.data.buffer.run_length(pos);
+
             self.move_cursor(pos)?;
 
             self.apply_attrs(real_attrs, cell.attrs())?;
-            self.writer.write_str(cell.text())?;
-            self.data.real_cursor.column += 1;
+
+            // A run of identical cells is written in a single call
+            if run > 1 {
+                self.writer.write_str(&self.data.buffer.text(&cell).repeat(run))?;
+                self.data.buffer.skip_cells(&mut indices, run - 1);
+            } else {
+                self.writer.write_str(&self.data.buffer.text(&cell))?;
+            }
+
+            self.data.real_cursor.column += run * cell.width();
 
             real_attrs = cell.attrs();
         }
@@ -243,15 +290,27 @@ impl<'a> ScreenWriteGuard<'a> {
         Ok(())
     }
 
-    fn apply_attrs(&mut self,
-            src: (Option<Color>, Option<Color>, Style),
-            dest: (Option<Color>, Option<Color>, Style)) -> io::Result<()> {
+    fn apply_attrs(&mut self, src: Theme, dest: Theme) -> io::Result<()> {
         if src != dest {
-            self.writer.set_attributes(dest.0, dest.1, dest.2)?;
+            self.writer.set_theme(dest)?;
         }
         Ok(())
     }
 
+    // Moves lines which have shifted vertically since the last refresh,
+    // so that only lines newly exposed by the shift need to be redrawn.
+    fn scroll_lines(&mut self) -> io::Result<()> {
+        if let Some(scroll) = self.data.buffer.find_scroll() {
+            let lines = self.data.buffer.size().lines;
+
+            if self.writer.scroll_lines(&scroll, lines)? {
+                self.data.buffer.apply_scroll(&scroll);
+            }
+        }
+
+        Ok(())
+    }
+
     fn move_cursor(&mut self, pos: Cursor) -> io::Result<()> {
         if self.data.real_cursor != pos {
             self.writer.move_cursor(pos)?;
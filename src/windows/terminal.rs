@@ -44,6 +44,8 @@ use winapi::um::winbase::{
 use winapi::um::wincon::{
     self,
     CreateConsoleScreenBuffer,
+    GetConsoleTitleW,
+    GetCurrentConsoleFont,
     WriteConsoleInputW,
     FillConsoleOutputAttribute,
     FillConsoleOutputCharacterA,
@@ -54,8 +56,10 @@ use winapi::um::wincon::{
     SetConsoleScreenBufferSize,
     GetConsoleScreenBufferInfo,
     SetConsoleTextAttribute,
+    SetConsoleTitleW,
     SetConsoleWindowInfo,
-    CHAR_INFO, CHAR_INFO_Char, CONSOLE_CURSOR_INFO, CONSOLE_SCREEN_BUFFER_INFO,
+    CHAR_INFO, CHAR_INFO_Char, CONSOLE_CURSOR_INFO, CONSOLE_FONT_INFO,
+    CONSOLE_SCREEN_BUFFER_INFO,
     COORD, SMALL_RECT,
     CONSOLE_TEXTMODE_BUFFER,
     INPUT_RECORD,
@@ -66,7 +70,7 @@ use winapi::um::wincon::{
     ENABLE_VIRTUAL_TERMINAL_INPUT,
     ENABLE_PROCESSED_INPUT,
     ENABLE_PROCESSED_OUTPUT, ENABLE_WRAP_AT_EOL_OUTPUT,
-    KEY_EVENT, MOUSE_EVENT, WINDOW_BUFFER_SIZE_EVENT,
+    FOCUS_EVENT, KEY_EVENT, MOUSE_EVENT, WINDOW_BUFFER_SIZE_EVENT,
 };
 use winapi::um::winuser;
 use winapi::um::winnt::{
@@ -74,14 +78,20 @@ use winapi::um::winnt::{
     FILE_SHARE_READ, FILE_SHARE_WRITE,
 };
 
-use crate::priv_util::{map_lock_result, map_try_lock_result};
+use crate::buffer::Scroll;
+use crate::priv_util::{map_lock_result, map_try_lock_result, ClickCounter};
 use crate::signal::{Signal, SignalSet};
 use crate::terminal::{
-    Color, Cursor, CursorMode, Event, Key, PrepareConfig, Size, Style, Theme,
-    MouseButton, MouseEvent, MouseInput, ModifierState,
+    Color, ColorDepth, Cursor, CursorMode, Event, Key, KeyEvent, PrepareConfig,
+    Size, Style, Theme, UnderlineStyle,
+    MouseButton, MouseEvent, MouseInput, ModifierState, PixelSize,
+    Query, Reply, Selection,
 };
 use crate::util::unctrl_lower;
 
+// Maximum length, in UTF-16 units, of a saved console title
+const MAX_TITLE_LEN: usize = 1024;
+
 pub struct Terminal {
     in_handle: HANDLE,
     default_attrs: WORD,
@@ -105,7 +115,10 @@ unsafe impl Sync for Terminal {}
 
 struct Reader {
     always_track_motion: bool,
+    report_focus: bool,
     prev_buttons: DWORD,
+    click_interval: Option<Duration>,
+    clicks: ClickCounter,
 }
 
 struct Writer {
@@ -113,11 +126,15 @@ struct Writer {
     fg: Option<Color>,
     bg: Option<Color>,
     style: Style,
+    // Console title saved before the first call to `set_title`
+    old_title: Option<Vec<WCHAR>>,
 }
 
 pub struct PrepareState {
     old_in_mode: DWORD,
     clear_handler: bool,
+    // Click interval in effect before `prepare`
+    prev_click_interval: Option<Duration>,
 }
 
 impl Terminal {
@@ -137,13 +154,17 @@ impl Terminal {
             old_out_mode,
             reader: Mutex::new(Reader{
                 always_track_motion: false,
+                report_focus: false,
                 prev_buttons: 0,
+                click_interval: None,
+                clicks: ClickCounter::new(),
             }),
             writer: Mutex::new(Writer{
                 out_handle,
                 fg: None,
                 bg: None,
                 style: Style::empty(),
+                old_title: None,
             }),
         })
     }
@@ -160,6 +181,10 @@ impl Terminal {
         "windows-console"
     }
 
+    pub fn color_depth(&self) -> ColorDepth {
+        ColorDepth::Color16
+    }
+
     pub fn size(&self) -> io::Result<Size> {
         self.lock_writer().size()
     }
@@ -227,6 +252,26 @@ impl Terminal {
         self.lock_reader().restore(state)
     }
 
+    pub fn pixel_size(&self) -> io::Result<Option<PixelSize>> {
+        self.lock_reader().pixel_size()
+    }
+
+    pub fn cell_size(&self) -> io::Result<Option<PixelSize>> {
+        self.lock_reader().cell_size()
+    }
+
+    pub fn cursor_position(&self, timeout: Option<Duration>) -> io::Result<Option<Cursor>> {
+        self.lock_reader().cursor_position(timeout)
+    }
+
+    pub fn query(&self, query: &Query, timeout: Option<Duration>) -> io::Result<Option<Reply>> {
+        self.lock_reader().query(query, timeout)
+    }
+
+    pub fn background_color(&self) -> io::Result<Option<Color>> {
+        self.lock_reader().background_color()
+    }
+
     pub fn wait_event(&self, timeout: Option<Duration>) -> io::Result<bool> {
         self.lock_reader().wait_event(timeout)
     }
@@ -249,6 +294,14 @@ impl Terminal {
         self.lock_writer().set_cursor_mode(mode)
     }
 
+    pub fn set_title(&self, title: &str) -> io::Result<()> {
+        self.lock_writer().set_title(title)
+    }
+
+    pub fn set_clipboard(&self, selection: Selection, text: &str) -> io::Result<()> {
+        self.lock_writer().set_clipboard(selection, text)
+    }
+
     pub fn clear_attributes(&self) -> io::Result<()> {
         self.lock_writer().clear_attributes()
     }
@@ -273,6 +326,18 @@ impl Terminal {
         self.lock_writer().set_bg(bg)
     }
 
+    pub fn set_underline_style(&self, style: UnderlineStyle) -> io::Result<()> {
+        self.lock_writer().set_underline_style(style)
+    }
+
+    pub fn set_underline_color(&self, color: Option<Color>) -> io::Result<()> {
+        self.lock_writer().set_underline_color(color)
+    }
+
+    pub fn set_link(&self, uri: Option<&str>) -> io::Result<()> {
+        self.lock_writer().set_link(uri)
+    }
+
     pub fn set_theme(&self, theme: Theme) -> io::Result<()> {
         self.lock_writer().set_theme(theme)
     }
@@ -324,7 +389,8 @@ impl Drop for Terminal {
     fn drop(&mut self) {
         let r = self.set_cursor_mode(CursorMode::Normal);
         let r2 = r.and_then(|_| {
-            let lock = self.lock_writer();
+            let mut lock = self.lock_writer();
+            lock.restore_title()?;
             unsafe { set_console_mode(lock.writer.out_handle, self.old_out_mode)?; }
             Ok(())
         });
@@ -355,6 +421,7 @@ impl<'a> TerminalReadGuard<'a> {
         let mut state = PrepareState{
             old_in_mode,
             clear_handler: false,
+            prev_click_interval: self.reader.click_interval,
         };
 
         let mut in_mode = old_in_mode;
@@ -377,11 +444,15 @@ impl<'a> TerminalReadGuard<'a> {
         // Enable or disable mouse events
         if config.enable_mouse {
             self.reader.always_track_motion = config.always_track_motion;
+            self.reader.click_interval = config.click_interval;
             in_mode |= ENABLE_MOUSE_INPUT;
         } else {
             in_mode &= !ENABLE_MOUSE_INPUT;
         }
 
+        // Focus events are always received; report them only if requested
+        self.reader.report_focus = config.enable_focus_events;
+
         // Disable text editing using mouse
         in_mode &= !ENABLE_QUICK_EDIT_MODE;
 
@@ -409,8 +480,88 @@ impl<'a> TerminalReadGuard<'a> {
         self.restore_with_lock(&mut writer, state)
     }
 
-    pub fn restore_with_lock(&mut self, _writer: &mut TerminalWriteGuard,
+    pub fn pixel_size(&mut self) -> io::Result<Option<PixelSize>> {
+        let mut writer = self.term.lock_writer();
+        self.pixel_size_with_lock(&mut writer)
+    }
+
+    pub fn pixel_size_with_lock(&mut self, writer: &mut TerminalWriteGuard)
+            -> io::Result<Option<PixelSize>> {
+        let size = writer.size()?;
+
+        Ok(writer.cell_size()?.map(|cell| PixelSize{
+            width: cell.width * size.columns,
+            height: cell.height * size.lines,
+        }))
+    }
+
+    pub fn cell_size(&mut self) -> io::Result<Option<PixelSize>> {
+        let mut writer = self.term.lock_writer();
+        self.cell_size_with_lock(&mut writer)
+    }
+
+    pub fn cell_size_with_lock(&mut self, writer: &mut TerminalWriteGuard)
+            -> io::Result<Option<PixelSize>> {
+        writer.cell_size()
+    }
+
+    pub fn cursor_position(&mut self, timeout: Option<Duration>)
+            -> io::Result<Option<Cursor>> {
+        let mut writer = self.term.lock_writer();
+        self.cursor_position_with_lock(&mut writer, timeout)
+    }
+
+    pub fn cursor_position_with_lock(&mut self, writer: &mut TerminalWriteGuard,
+            _timeout: Option<Duration>) -> io::Result<Option<Cursor>> {
+        // The console reports the cursor position directly
+        let info = writer.get_info()?;
+        let pos = info.dwCursorPosition;
+
+        Ok(Some(Cursor{
+            line: (pos.Y - info.srWindow.Top).max(0) as usize,
+            column: pos.X as usize,
+        }))
+    }
+
+    pub fn query(&mut self, query: &Query, timeout: Option<Duration>)
+            -> io::Result<Option<Reply>> {
+        let mut writer = self.term.lock_writer();
+        self.query_with_lock(&mut writer, query, timeout)
+    }
+
+    pub fn query_with_lock(&mut self, _writer: &mut TerminalWriteGuard,
+            _query: &Query, _timeout: Option<Duration>) -> io::Result<Option<Reply>> {
+        // Console input is read as input records, rather than
+        // as a byte stream from which replies could be read.
+        Ok(None)
+    }
+
+    pub fn background_color(&mut self) -> io::Result<Option<Color>> {
+        let mut writer = self.term.lock_writer();
+        self.background_color_with_lock(&mut writer)
+    }
+
+    pub fn background_color_with_lock(&mut self, _writer: &mut TerminalWriteGuard)
+            -> io::Result<Option<Color>> {
+        let attrs = self.term.default_attrs;
+        let mut n = 0;
+
+        if attrs & wincon::BACKGROUND_RED as WORD != 0 { n |= 1; }
+        if attrs & wincon::BACKGROUND_GREEN as WORD != 0 { n |= 2; }
+        if attrs & wincon::BACKGROUND_BLUE as WORD != 0 { n |= 4; }
+        if attrs & wincon::BACKGROUND_INTENSITY as WORD != 0 { n |= 8; }
+
+        let (r, g, b) = Color::Indexed(n).to_rgb();
+
+        Ok(Some(Color::Rgb(r, g, b)))
+    }
+
+    pub fn restore_with_lock(&mut self, writer: &mut TerminalWriteGuard,
             state: PrepareState) -> io::Result<()> {
+        self.reader.click_interval = state.prev_click_interval;
+
+        writer.restore_title()?;
+
         unsafe {
             if state.clear_handler {
                 result_bool(SetConsoleCtrlHandler(Some(ctrl_handler), FALSE))?;
@@ -452,11 +603,17 @@ impl<'a> TerminalReadGuard<'a> {
             let event = event[0];
 
             if let Some(key) = key_press_event(&event) {
-                Ok(Some(Event::Key(key)))
+                Ok(Some(key.into_event()))
             } else if let Some(mouse) = self.mouse_event(&event) {
                 Ok(Some(Event::Mouse(mouse)))
             } else if let Some(size) = size_event(&event) {
                 Ok(Some(Event::Resize(size)))
+            } else if let Some(focus) = self.focus_event(&event) {
+                if focus {
+                    Ok(Some(Event::FocusGained))
+                } else {
+                    Ok(Some(Event::FocusLost))
+                }
             } else {
                 Ok(Some(Event::NoEvent))
             }
@@ -514,6 +671,16 @@ impl<'a> TerminalReadGuard<'a> {
         Ok(Some(Event::Raw(n as usize)))
     }
 
+    // Returns whether focus was gained or lost
+    fn focus_event(&self, event: &INPUT_RECORD) -> Option<bool> {
+        if event.EventType == FOCUS_EVENT && self.reader.report_focus {
+            let focus = unsafe { event.Event.FocusEvent() };
+            Some(focus.bSetFocus != FALSE)
+        } else {
+            None
+        }
+    }
+
     fn mouse_event(&mut self, event: &INPUT_RECORD) -> Option<MouseEvent> {
         if event.EventType == MOUSE_EVENT {
             let mouse = unsafe { event.Event.MouseEvent() };
@@ -527,6 +694,14 @@ impl<'a> TerminalReadGuard<'a> {
                 } else {
                     MouseInput::WheelDown
                 }
+            } else if mouse.dwEventFlags & wincon::MOUSE_HWHEELED != 0 {
+                let direction = (mouse.dwButtonState >> 16) as i16;
+
+                if direction > 0 {
+                    MouseInput::WheelRight
+                } else {
+                    MouseInput::WheelLeft
+                }
             } else {
                 let prev_buttons = self.reader.prev_buttons;
                 let now_buttons = mouse.dwButtonState;
@@ -534,11 +709,17 @@ impl<'a> TerminalReadGuard<'a> {
                 self.reader.prev_buttons = mouse.dwButtonState;
 
                 if prev_buttons == now_buttons {
-                    if now_buttons == 0 && !self.reader.always_track_motion {
-                        return None;
+                    if now_buttons == 0 {
+                        if !self.reader.always_track_motion {
+                            return None;
+                        }
+
+                        MouseInput::Motion
+                    } else {
+                        // Report the lowest numbered button held
+                        let bit = now_buttons & now_buttons.wrapping_neg();
+                        MouseInput::Drag(bit_to_button(bit))
                     }
-
-                    MouseInput::Motion
                 } else {
                     button_changed(prev_buttons, now_buttons)?
                 }
@@ -546,22 +727,19 @@ impl<'a> TerminalReadGuard<'a> {
 
             let position = coord_to_cursor(mouse.dwMousePosition);
 
-            let mut mods = ModifierState::empty();
-
-            if has_alt(mouse.dwControlKeyState) {
-                mods |= ModifierState::ALT;
-            }
-            if has_ctrl(mouse.dwControlKeyState) {
-                mods |= ModifierState::CTRL;
-            }
-            if has_shift(mouse.dwControlKeyState) {
-                mods |= ModifierState::SHIFT;
-            }
+            let click_count = match input {
+                MouseInput::ButtonPressed(button) =>
+                    self.reader.clicks.click(
+                        button, position, self.reader.click_interval),
+                _ => 0
+            };
 
             Some(MouseEvent{
                 position,
                 input,
-                modifiers: mods,
+                modifiers: modifier_state(mouse.dwControlKeyState),
+                click_count,
+                pixel_position: None,
             })
         } else {
             None
@@ -615,6 +793,25 @@ impl<'a> TerminalWriteGuard<'a> {
         unsafe { console_size(self.writer.out_handle) }
     }
 
+    // Returns the size of the console font
+    fn cell_size(&self) -> io::Result<Option<PixelSize>> {
+        let mut info: CONSOLE_FONT_INFO = unsafe { zeroed() };
+
+        result_bool(unsafe { GetCurrentConsoleFont(
+            self.writer.out_handle, FALSE, &mut info) })?;
+
+        let size = info.dwFontSize;
+
+        if size.X > 0 && size.Y > 0 {
+            Ok(Some(PixelSize{
+                width: size.X as usize,
+                height: size.Y as usize,
+            }))
+        } else {
+            Ok(None)
+        }
+    }
+
     pub fn flush(&mut self) -> io::Result<()> {
         Ok(())
     }
@@ -687,6 +884,38 @@ impl<'a> TerminalWriteGuard<'a> {
         })
     }
 
+    pub fn scroll_lines(&mut self, scroll: &Scroll, _lines: usize) -> io::Result<bool> {
+        let info = self.get_info()?;
+
+        let region = SMALL_RECT{
+            Top: to_short(scroll.top),
+            Bottom: to_short(scroll.bottom - 1),
+            Left: 0,
+            Right: info.dwSize.X - 1,
+        };
+
+        // Text moved outside the region is clipped and
+        // the space left behind is filled with blank cells
+        let dest = COORD{
+            X: 0,
+            Y: (scroll.top as isize - scroll.n) as SHORT,
+        };
+
+        let fill = CHAR_INFO{
+            Char: unicode_char(b' ' as WCHAR),
+            Attributes: self.term.default_attrs,
+        };
+
+        result_bool(unsafe { ScrollConsoleScreenBufferW(
+            self.writer.out_handle,
+            &region,
+            &region,
+            dest,
+            &fill) })?;
+
+        Ok(true)
+    }
+
     pub fn clear_to_line_end(&mut self) -> io::Result<()> {
         let info = self.get_info()?;
 
@@ -750,6 +979,38 @@ impl<'a> TerminalWriteGuard<'a> {
         result_bool(unsafe { SetConsoleCursorInfo(self.writer.out_handle, &info) })
     }
 
+    pub fn set_title(&mut self, title: &str) -> io::Result<()> {
+        if self.writer.old_title.is_none() {
+            let mut buf = vec![0; MAX_TITLE_LEN];
+
+            let n = unsafe { GetConsoleTitleW(
+                buf.as_mut_ptr(), buf.len() as DWORD) };
+
+            buf.truncate(n as usize);
+            buf.push(0);
+            self.writer.old_title = Some(buf);
+        }
+
+        let buf = OsStr::new(title).encode_wide()
+            .filter(|&ch| ch != 0)
+            .chain(Some(0))
+            .collect::<Vec<_>>();
+
+        result_bool(unsafe { SetConsoleTitleW(buf.as_ptr()) })
+    }
+
+    pub fn set_clipboard(&mut self, _selection: Selection, _text: &str) -> io::Result<()> {
+        // The console does not provide access to the clipboard
+        Ok(())
+    }
+
+    fn restore_title(&mut self) -> io::Result<()> {
+        if let Some(title) = self.writer.old_title.take() {
+            result_bool(unsafe { SetConsoleTitleW(title.as_ptr()) })?;
+        }
+        Ok(())
+    }
+
     pub fn clear_attributes(&mut self) -> io::Result<()> {
         self.set_attributes(None, None, Style::empty())
     }
@@ -801,6 +1062,20 @@ impl<'a> TerminalWriteGuard<'a> {
         Ok(())
     }
 
+    // Windows console does not support underline styles or colors
+    pub fn set_underline_style(&mut self, _style: UnderlineStyle) -> io::Result<()> {
+        Ok(())
+    }
+
+    pub fn set_underline_color(&mut self, _color: Option<Color>) -> io::Result<()> {
+        Ok(())
+    }
+
+    // Nor does it support hyperlinks
+    pub fn set_link(&mut self, _uri: Option<&str>) -> io::Result<()> {
+        Ok(())
+    }
+
     pub fn set_theme(&mut self, theme: Theme) -> io::Result<()> {
         self.set_attributes(theme.fg, theme.bg, theme.style)
     }
@@ -822,12 +1097,12 @@ impl<'a> TerminalWriteGuard<'a> {
         let mut attrs = self.term.default_attrs;
 
         if let Some(fg) = self.writer.fg {
-            attrs &= !fg_code(Color::White);
+            attrs &= !fg_code(Color::BrightWhite);
             attrs |= fg_code(fg);
         }
 
         if let Some(bg) = self.writer.bg {
-            attrs &= !bg_code(Color::White);
+            attrs &= !bg_code(Color::BrightWhite);
             attrs |= bg_code(bg);
         }
 
@@ -943,29 +1218,55 @@ fn as_millis(timeout: Option<Duration>) -> DWORD {
 }
 
 fn fg_code(color: Color) -> WORD {
-    (match color {
-        Color::Black => 0,
-        Color::Blue => wincon::FOREGROUND_BLUE,
-        Color::Cyan => wincon::FOREGROUND_BLUE | wincon::FOREGROUND_GREEN,
-        Color::Green => wincon::FOREGROUND_GREEN,
-        Color::Magenta => wincon::FOREGROUND_BLUE | wincon::FOREGROUND_RED,
-        Color::Red => wincon::FOREGROUND_RED,
-        Color::White => wincon::FOREGROUND_RED | wincon::FOREGROUND_GREEN | wincon::FOREGROUND_BLUE,
-        Color::Yellow => wincon::FOREGROUND_RED | wincon::FOREGROUND_GREEN,
-    }) as WORD
+    let color = color.quantize(ColorDepth::Color16);
+    let (r, g, b) = color_bits(color);
+    let mut code = 0;
+
+    if r { code |= wincon::FOREGROUND_RED; }
+    if g { code |= wincon::FOREGROUND_GREEN; }
+    if b { code |= wincon::FOREGROUND_BLUE; }
+    if is_bright(color) { code |= wincon::FOREGROUND_INTENSITY; }
+
+    code as WORD
 }
 
 fn bg_code(color: Color) -> WORD {
-    (match color {
-        Color::Black => 0,
-        Color::Blue => wincon::BACKGROUND_BLUE,
-        Color::Cyan => wincon::BACKGROUND_BLUE | wincon::BACKGROUND_GREEN,
-        Color::Green => wincon::BACKGROUND_GREEN,
-        Color::Magenta => wincon::BACKGROUND_BLUE | wincon::BACKGROUND_RED,
-        Color::Red => wincon::BACKGROUND_RED,
-        Color::White => wincon::BACKGROUND_RED | wincon::BACKGROUND_GREEN | wincon::BACKGROUND_BLUE,
-        Color::Yellow => wincon::BACKGROUND_RED | wincon::BACKGROUND_GREEN,
-    }) as WORD
+    let color = color.quantize(ColorDepth::Color16);
+    let (r, g, b) = color_bits(color);
+    let mut code = 0;
+
+    if r { code |= wincon::BACKGROUND_RED; }
+    if g { code |= wincon::BACKGROUND_GREEN; }
+    if b { code |= wincon::BACKGROUND_BLUE; }
+    if is_bright(color) { code |= wincon::BACKGROUND_INTENSITY; }
+
+    code as WORD
+}
+
+// Returns whether the red, green, and blue components of a color are set.
+fn color_bits(color: Color) -> (bool, bool, bool) {
+    match color {
+        Color::Black | Color::BrightBlack =>        (false, false, false),
+        Color::Blue | Color::BrightBlue =>          (false, false, true),
+        Color::Cyan | Color::BrightCyan =>          (false, true,  true),
+        Color::Green | Color::BrightGreen =>        (false, true,  false),
+        Color::Magenta | Color::BrightMagenta =>    (true,  false, true),
+        Color::Red | Color::BrightRed =>            (true,  false, false),
+        Color::White | Color::BrightWhite =>        (true,  true,  true),
+        Color::Yellow | Color::BrightYellow =>      (true,  true,  false),
+        // Other colors are quantized to one of the above
+        Color::Indexed(_) | Color::Rgb(..) =>
+            unreachable!("color is not quantized")
+    }
+}
+
+fn is_bright(color: Color) -> bool {
+    match color {
+        Color::BrightBlack | Color::BrightBlue | Color::BrightCyan |
+        Color::BrightGreen | Color::BrightMagenta | Color::BrightRed |
+        Color::BrightWhite | Color::BrightYellow => true,
+        _ => false
+    }
 }
 
 fn style_code(style: Style) -> WORD {
@@ -980,8 +1281,8 @@ fn style_code(style: Style) -> WORD {
 }
 
 fn swap_colors(code: WORD) -> WORD {
-    let fg_mask = fg_code(Color::White);
-    let bg_mask = bg_code(Color::White);
+    let fg_mask = fg_code(Color::BrightWhite);
+    let bg_mask = bg_code(Color::BrightWhite);
 
     let fg_shift = fg_mask.trailing_zeros();
     let bg_shift = bg_mask.trailing_zeros();
@@ -1135,6 +1436,22 @@ fn has_shift(state: DWORD) -> bool {
     state & wincon::SHIFT_PRESSED != 0
 }
 
+fn modifier_state(state: DWORD) -> ModifierState {
+    let mut mods = ModifierState::empty();
+
+    if has_alt(state) {
+        mods |= ModifierState::ALT;
+    }
+    if has_ctrl(state) {
+        mods |= ModifierState::CTRL;
+    }
+    if has_shift(state) {
+        mods |= ModifierState::SHIFT;
+    }
+
+    mods
+}
+
 fn to_dword(n: usize) -> DWORD {
     if n > DWORD::max_value() as usize {
         DWORD::max_value()
@@ -1165,7 +1482,7 @@ fn to_short_neg(n: usize) -> SHORT {
     }
 }
 
-fn key_press_event(event: &INPUT_RECORD) -> Option<Key> {
+fn key_press_event(event: &INPUT_RECORD) -> Option<KeyEvent> {
     if event.EventType == KEY_EVENT {
         let key = unsafe { event.Event.KeyEvent() };
 
@@ -1173,6 +1490,8 @@ fn key_press_event(event: &INPUT_RECORD) -> Option<Key> {
             return None;
         }
 
+        let mut modifiers = modifier_state(key.dwControlKeyState);
+
         let key = match key.wVirtualKeyCode as c_int {
             winuser::VK_BACK => Key::Backspace,
             winuser::VK_RETURN => Key::Enter,
@@ -1188,6 +1507,7 @@ fn key_press_event(event: &INPUT_RECORD) -> Option<Key> {
             winuser::VK_END => Key::End,
             winuser::VK_PRIOR => Key::PageUp,
             winuser::VK_NEXT => Key::PageDown,
+            winuser::VK_CLEAR => Key::Begin,
             winuser::VK_F1 => Key::F(1),
             winuser::VK_F2 => Key::F(2),
             winuser::VK_F3 => Key::F(3),
@@ -1201,14 +1521,14 @@ fn key_press_event(event: &INPUT_RECORD) -> Option<Key> {
             winuser::VK_F11 => Key::F(11),
             winuser::VK_F12 => Key::F(12),
             _ => {
-                if has_alt(key.dwControlKeyState) {
-                    return None;
-                }
-
                 let is_ctrl = has_ctrl(key.dwControlKeyState);
 
                 let u_char = unsafe { *key.uChar.UnicodeChar() };
 
+                // Ctrl and Shift are reflected in the character value;
+                // only Alt is reported as a modifier.
+                modifiers &= ModifierState::ALT;
+
                 if u_char != 0 {
                     match char::from_u32(u_char as u32) {
                         Some(ch) if is_ctrl => Key::Ctrl(unctrl_lower(ch)),
@@ -1221,7 +1541,7 @@ fn key_press_event(event: &INPUT_RECORD) -> Option<Key> {
             }
         };
 
-        Some(key)
+        Some(KeyEvent::new(key, modifiers))
     } else {
         None
     }